@@ -0,0 +1,192 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::net::UdpSocket;
+use tokio::time::interval;
+use tracing::{debug, warn};
+
+use crate::backends::bedrock::raknet::{
+    is_open_connection_request_1, open_connection_reply_1, parse_open_connection_request_2,
+    parse_unconnected_ping, unconnected_pong, OPEN_CONNECTION_REPLY_1,
+};
+use crate::backends::host_table::HostTable;
+use crate::backends::udp::session::Sessions;
+use crate::configuration::BedrockPongConfig;
+
+const MAX_DATAGRAM_SIZE: usize = 65535;
+const PENDING_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub(crate) async fn listen(
+    socket: UdpSocket,
+    hosts: Arc<HostTable<String>>,
+    pong: BedrockPongConfig,
+    idle_timeout: Duration,
+    max_sessions: usize,
+) -> io::Result<()> {
+    let socket = Arc::new(socket);
+
+    let server_guid = RandomState::new().build_hasher().finish() as i64;
+    let status = pong_status(&pong, server_guid, socket.local_addr()?.port());
+
+    // The backend already answered the first connection request of the client
+    let sessions = Sessions::new("bedrock", Arc::clone(&socket), idle_timeout, max_sessions)
+        .with_reply_filter(|datagram| datagram.first() != Some(&OPEN_CONNECTION_REPLY_1));
+
+    // Spoofed clients can fill it as well as the sessions
+    let mut pending_requests = PendingRequests::new(max_sessions);
+    let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+    let mut expiry = interval(PENDING_REQUEST_TIMEOUT);
+
+    loop {
+        let received = tokio::select! {
+            received = socket.recv_from(&mut buffer) => received,
+            _ = expiry.tick() => {
+                pending_requests.expire();
+                continue;
+            }
+        };
+        let (length, address) = match received {
+            Ok(received) => received,
+            Err(err) => {
+                debug!("could not receive datagram; error={err}");
+                continue;
+            }
+        };
+        let datagram = &buffer[..length];
+
+        if let Some(time) = parse_unconnected_ping(datagram) {
+            let pong = unconnected_pong(time, server_guid, &status);
+            if let Err(err) = socket.send_to(&pong, address).await {
+                debug!("could not answer ping of {address}; error={err}");
+            }
+            continue;
+        }
+
+        if is_open_connection_request_1(datagram) {
+            if !pending_requests.insert(address, datagram) {
+                debug!("dropped connection request of {address}, too many pending requests");
+                continue;
+            }
+            // The client reconnects, possibly to another backend
+            sessions.close(address);
+
+            let reply = open_connection_reply_1(datagram, server_guid);
+            if let Err(err) = socket.send_to(&reply, address).await {
+                debug!("could not reply to {address}; error={err}");
+            }
+            continue;
+        }
+
+        if sessions.forward(address, datagram).await {
+            continue;
+        }
+
+        let Some(server_address) = parse_open_connection_request_2(datagram) else {
+            debug!("dropped datagram of {address} without session");
+            continue;
+        };
+        let Some(target) = hosts.get(&server_address.to_string()) else {
+            warn!("bedrock:{address} no route for server address {server_address}");
+            continue;
+        };
+
+        let first_request = pending_requests.take(address);
+        let requests = first_request.into_iter().chain([datagram.to_vec()]);
+        sessions.open(address, target.clone(), requests.collect());
+    }
+}
+
+/// First connection requests, answered by the proxy and replayed to the backend once the second
+/// request tells which backend the client connects to.
+struct PendingRequests {
+    requests: HashMap<SocketAddr, (Instant, Vec<u8>)>,
+    capacity: usize,
+}
+
+impl PendingRequests {
+    fn new(capacity: usize) -> Self {
+        Self {
+            requests: HashMap::new(),
+            capacity,
+        }
+    }
+
+    /// Keeps the request of a client, unless too many clients have one pending.
+    fn insert(&mut self, address: SocketAddr, request: &[u8]) -> bool {
+        if self.requests.len() >= self.capacity && !self.requests.contains_key(&address) {
+            return false;
+        }
+        self.requests
+            .insert(address, (Instant::now(), request.to_vec()));
+        true
+    }
+
+    fn take(&mut self, address: SocketAddr) -> Option<Vec<u8>> {
+        self.requests.remove(&address).map(|(_, request)| request)
+    }
+
+    fn expire(&mut self) {
+        self.requests
+            .retain(|_, (received_at, _)| received_at.elapsed() < PENDING_REQUEST_TIMEOUT);
+    }
+}
+
+/// Status advertised in the server list, separated by semicolons.
+fn pong_status(pong: &BedrockPongConfig, server_guid: i64, port: u16) -> String {
+    format!(
+        "MCPE;{};{};{};{};{};{};{};Survival;1;{port};{port};",
+        pong.motd,
+        pong.protocol,
+        pong.version,
+        pong.online_players,
+        pong.max_players,
+        server_guid as u64,
+        pong.sub_motd.as_deref().unwrap_or(&pong.motd),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_drop_requests_beyond_the_capacity() {
+        // Given
+        let mut pending_requests = PendingRequests::new(1);
+        let first = SocketAddr::from(([10, 0, 0, 1], 19132));
+        let second = SocketAddr::from(([10, 0, 0, 2], 19132));
+        assert!(pending_requests.insert(first, b"first"));
+
+        // When
+        let inserted = pending_requests.insert(second, b"second");
+
+        // Then
+        assert!(!inserted);
+        assert!(pending_requests.insert(first, b"again"));
+        assert_eq!(pending_requests.take(first), Some(b"again".to_vec()));
+        assert!(pending_requests.insert(second, b"second"));
+    }
+
+    #[test]
+    fn should_format_pong_status() {
+        // Given
+        let pong = BedrockPongConfig {
+            motd: "My Network".to_string(),
+            ..Default::default()
+        };
+
+        // When
+        let status = pong_status(&pong, 42, 19132);
+
+        // Then
+        assert_eq!(
+            status,
+            "MCPE;My Network;685;1.21.0;0;20;42;My Network;Survival;1;19132;19132;"
+        );
+    }
+}
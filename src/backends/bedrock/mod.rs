@@ -0,0 +1,37 @@
+use crate::backends::host_table::HostTable;
+use crate::backends::start_listeners;
+use crate::configuration::{BedrockHost, BedrockPongConfig, ListenerConfig};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+mod bedrock_proxy;
+mod raknet;
+
+/// Hosts are matched on the server address reported by the client, such as `203.0.113.5:19132`,
+/// `*:19133` for any address on a port, or `*`.
+pub(crate) fn start_bedrock_proxy(
+    listener: ListenerConfig,
+    hosts: Vec<BedrockHost>,
+    pong: BedrockPongConfig,
+    idle_timeout_seconds: u64,
+    max_sessions: usize,
+) -> JoinHandle<()> {
+    let routes = hosts
+        .into_iter()
+        .map(|host| (host.server_address, host.target))
+        .collect::<HostTable<String>>();
+
+    let routes = Arc::new(routes);
+    let idle_timeout = Duration::from_secs(idle_timeout_seconds);
+
+    start_listeners("Bedrock", listener, move |socket| {
+        bedrock_proxy::listen(
+            socket,
+            Arc::clone(&routes),
+            pong.clone(),
+            idle_timeout,
+            max_sessions,
+        )
+    })
+}
@@ -0,0 +1,154 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+pub(crate) const UNCONNECTED_PING: u8 = 0x01;
+pub(crate) const UNCONNECTED_PING_OPEN_CONNECTIONS: u8 = 0x02;
+pub(crate) const OPEN_CONNECTION_REQUEST_1: u8 = 0x05;
+pub(crate) const OPEN_CONNECTION_REPLY_1: u8 = 0x06;
+pub(crate) const OPEN_CONNECTION_REQUEST_2: u8 = 0x07;
+const UNCONNECTED_PONG: u8 = 0x1c;
+
+/// Marks the offline messages, sent before a connection is established.
+const MAGIC: [u8; 16] = [
+    0x00, 0xff, 0xff, 0x00, 0xfe, 0xfe, 0xfe, 0xfe, 0xfd, 0xfd, 0xfd, 0xfd, 0x12, 0x34, 0x56, 0x78,
+];
+
+/// Returns the time of an unconnected ping, echoed in the pong.
+pub(crate) fn parse_unconnected_ping(datagram: &[u8]) -> Option<i64> {
+    match datagram.first()? {
+        &UNCONNECTED_PING | &UNCONNECTED_PING_OPEN_CONNECTIONS => {}
+        _ => return None,
+    }
+    if datagram.get(9..25)? != MAGIC {
+        return None;
+    }
+
+    Some(i64::from_be_bytes(datagram[1..9].try_into().ok()?))
+}
+
+pub(crate) fn unconnected_pong(time: i64, server_guid: i64, status: &str) -> Vec<u8> {
+    let mut datagram = vec![UNCONNECTED_PONG];
+    datagram.extend_from_slice(&time.to_be_bytes());
+    datagram.extend_from_slice(&server_guid.to_be_bytes());
+    datagram.extend_from_slice(&MAGIC);
+    datagram.extend_from_slice(&(status.len() as u16).to_be_bytes());
+    datagram.extend_from_slice(status.as_bytes());
+    datagram
+}
+
+pub(crate) fn is_open_connection_request_1(datagram: &[u8]) -> bool {
+    datagram.first() == Some(&OPEN_CONNECTION_REQUEST_1) && datagram.get(1..17) == Some(&MAGIC)
+}
+
+/// The client pads the request up to its MTU, minus the IP and UDP headers.
+pub(crate) fn open_connection_reply_1(request: &[u8], server_guid: i64) -> Vec<u8> {
+    let mtu = (request.len() + 28).min(u16::MAX as usize) as u16;
+
+    let mut datagram = vec![OPEN_CONNECTION_REPLY_1];
+    datagram.extend_from_slice(&MAGIC);
+    datagram.extend_from_slice(&server_guid.to_be_bytes());
+    datagram.push(0x00); // No security
+    datagram.extend_from_slice(&mtu.to_be_bytes());
+    datagram
+}
+
+/// Returns the server address the client connects to, as reported in the request.
+pub(crate) fn parse_open_connection_request_2(datagram: &[u8]) -> Option<SocketAddr> {
+    if datagram.first()? != &OPEN_CONNECTION_REQUEST_2 || datagram.get(1..17)? != MAGIC {
+        return None;
+    }
+
+    read_address(datagram.get(17..)?)
+}
+
+fn read_address(bytes: &[u8]) -> Option<SocketAddr> {
+    match bytes.first()? {
+        4 => {
+            // The bytes of IPv4 addresses are inverted
+            let ip = bytes
+                .get(1..5)?
+                .iter()
+                .map(|byte| !byte)
+                .collect::<Vec<_>>();
+            let port = u16::from_be_bytes(bytes.get(5..7)?.try_into().ok()?);
+            Some(SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3])),
+                port,
+            ))
+        }
+        6 => {
+            // Family, port, flow info, address and scope id of a sockaddr_in6
+            let port = u16::from_be_bytes(bytes.get(3..5)?.try_into().ok()?);
+            let ip: [u8; 16] = bytes.get(9..25)?.try_into().ok()?;
+            Some(SocketAddr::new(IpAddr::V6(Ipv6Addr::from(ip)), port))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_unconnected_ping() {
+        // Given
+        let mut datagram = vec![UNCONNECTED_PING];
+        datagram.extend_from_slice(&1234i64.to_be_bytes());
+        datagram.extend_from_slice(&MAGIC);
+        datagram.extend_from_slice(&42i64.to_be_bytes());
+
+        // When
+        let time = parse_unconnected_ping(&datagram);
+
+        // Then
+        assert_eq!(time, Some(1234));
+    }
+
+    #[test]
+    fn should_build_unconnected_pong() {
+        // Given
+        let status = "MCPE;Proxy;685;1.21.0;0;20;1;Proxy;Survival;1;19132;19133;";
+
+        // When
+        let datagram = unconnected_pong(1234, 1, status);
+
+        // Then
+        assert_eq!(datagram[0], UNCONNECTED_PONG);
+        assert_eq!(&datagram[1..9], &1234i64.to_be_bytes());
+        assert_eq!(&datagram[17..33], &MAGIC);
+        assert_eq!(&datagram[35..], status.as_bytes());
+    }
+
+    #[test]
+    fn should_reply_with_mtu_of_the_request() {
+        // Given
+        let mut request = vec![OPEN_CONNECTION_REQUEST_1];
+        request.extend_from_slice(&MAGIC);
+        request.push(11);
+        request.resize(1464, 0);
+
+        // When
+        let reply = open_connection_reply_1(&request, 1);
+
+        // Then
+        assert!(is_open_connection_request_1(&request));
+        assert_eq!(&reply[reply.len() - 2..], &1492u16.to_be_bytes());
+    }
+
+    #[test]
+    fn should_parse_server_address_of_request_2() {
+        // Given
+        let mut request = vec![OPEN_CONNECTION_REQUEST_2];
+        request.extend_from_slice(&MAGIC);
+        request.extend_from_slice(&[4, !192, !168, !1, !10]);
+        request.extend_from_slice(&19133u16.to_be_bytes());
+        request.extend_from_slice(&1492u16.to_be_bytes());
+        request.extend_from_slice(&42i64.to_be_bytes());
+
+        // When
+        let address = parse_open_connection_request_2(&request);
+
+        // Then
+        assert_eq!(address, Some("192.168.1.10:19133".parse().unwrap()));
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+
+/// Routes indexed by hostname. Hostnames are either exact names, wildcards such as
+/// `*.example.com` matching any subdomain, or `*` matching any hostname. Exact names take
+/// precedence over wildcards, and longer wildcards over shorter ones.
+pub(crate) struct HostTable<T> {
+    exact: HashMap<String, T>,
+    wildcards: Vec<(String, T)>,
+    default: Option<T>,
+}
+
+impl<T> HostTable<T> {
+    pub(crate) fn get(&self, hostname: &str) -> Option<&T> {
+        let hostname = normalize(hostname);
+
+        if let Some(value) = self.exact.get(&hostname) {
+            return Some(value);
+        }
+
+        self.wildcards
+            .iter()
+            .find(|(suffix, _)| hostname.ends_with(suffix.as_str()))
+            .map(|(_, value)| value)
+            .or(self.default.as_ref())
+    }
+
+    fn insert(&mut self, hostname: &str, value: T) {
+        let hostname = normalize(hostname);
+
+        if hostname == "*" {
+            self.default = Some(value);
+        } else if let Some(suffix) = hostname.strip_prefix('*') {
+            self.wildcards.push((suffix.to_string(), value));
+            self.wildcards
+                .sort_by_key(|(suffix, _)| std::cmp::Reverse(suffix.len()));
+        } else {
+            self.exact.insert(hostname, value);
+        }
+    }
+}
+
+impl<T> HostTable<Vec<T>> {
+    /// Groups the values sharing a hostname, in order of declaration.
+    pub(crate) fn grouped<I: IntoIterator<Item = (String, T)>>(iter: I) -> Self {
+        let mut groups: Vec<(String, Vec<T>)> = Vec::new();
+
+        for (hostname, value) in iter {
+            let hostname = normalize(&hostname);
+            match groups.iter_mut().find(|(name, _)| *name == hostname) {
+                Some((_, values)) => values.push(value),
+                None => groups.push((hostname, vec![value])),
+            }
+        }
+
+        groups.into_iter().collect()
+    }
+}
+
+impl<T> FromIterator<(String, T)> for HostTable<T> {
+    fn from_iter<I: IntoIterator<Item = (String, T)>>(iter: I) -> Self {
+        let mut table = HostTable {
+            exact: HashMap::new(),
+            wildcards: Vec::new(),
+            default: None,
+        };
+
+        for (hostname, value) in iter {
+            table.insert(&hostname, value);
+        }
+
+        table
+    }
+}
+
+/// Hostnames are case-insensitive and may be sent as fully qualified names with a trailing dot.
+pub(crate) fn normalize(hostname: &str) -> String {
+    hostname.trim_end_matches('.').to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> HostTable<&'static str> {
+        vec![
+            ("example.com".to_string(), "exact"),
+            ("*.example.com".to_string(), "wildcard"),
+            ("*.play.example.com".to_string(), "longer wildcard"),
+            ("*".to_string(), "default"),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    #[test]
+    fn should_prefer_exact_hostname() {
+        assert_eq!(table().get("Example.com."), Some(&"exact"));
+    }
+
+    #[test]
+    fn should_match_longest_wildcard() {
+        assert_eq!(table().get("www.example.com"), Some(&"wildcard"));
+        assert_eq!(table().get("eu.play.example.com"), Some(&"longer wildcard"));
+    }
+
+    #[test]
+    fn should_fallback_to_default() {
+        assert_eq!(table().get("example.org"), Some(&"default"));
+    }
+
+    #[test]
+    fn should_group_values_by_hostname() {
+        let table = HostTable::grouped(vec![
+            ("example.com".to_string(), 1),
+            ("*.example.com".to_string(), 2),
+            ("Example.com".to_string(), 3),
+        ]);
+
+        assert_eq!(table.get("example.com"), Some(&vec![1, 3]));
+        assert_eq!(table.get("www.example.com"), Some(&vec![2]));
+    }
+
+    #[test]
+    fn should_not_match_without_default() {
+        let table = vec![("*.example.com".to_string(), "wildcard")]
+            .into_iter()
+            .collect::<HostTable<_>>();
+
+        assert_eq!(table.get("example.com"), None);
+        assert_eq!(table.get("notexample.com"), None);
+    }
+}
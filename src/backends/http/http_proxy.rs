@@ -0,0 +1,227 @@
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::time::timeout;
+use tracing::{debug, error};
+
+use crate::backends::host_table::HostTable;
+use crate::backends::http::request::{
+    close_response, parse_request_head, RequestError, RequestHead, ResponseHead,
+};
+use crate::backends::http::HttpRoute;
+use crate::backends::proxy_connection::{proxy_connection_with, ProxyConnectionError};
+use crate::backends::stream::{Listener, PeerAddress, Stream};
+use crate::backends::supervisor::ActiveConnection;
+
+const REQUEST_HEAD_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_REQUEST_HEAD_SIZE: usize = 16 * 1024;
+const MAX_RESPONSE_HEAD_SIZE: usize = 64 * 1024;
+
+#[derive(Error, Debug)]
+enum HttpRedirectError {
+    #[error("could not read request; error={0}")]
+    ReadFailed(std::io::Error),
+    #[error("request head was not received in time")]
+    Timeout,
+    #[error("connection closed before the end of the request head")]
+    ConnectionClosed,
+    #[error("request head is too large")]
+    TooLarge,
+    #[error("invalid request; error={0}")]
+    InvalidRequest(RequestError),
+    #[error("request without host")]
+    MissingHost,
+    #[error("no route for host {0}")]
+    UnknownHost(String),
+    #[error("no route for path {1} of host {0}")]
+    UnknownPath(String, String),
+    #[error("could not write response; error={0}")]
+    WriteFailed(std::io::Error),
+    #[error("{0}")]
+    ProxyError(ProxyConnectionError),
+}
+
+impl HttpRedirectError {
+    /// Response sent to the client before closing the connection, if it is still readable.
+    fn response(&self) -> Option<&'static str> {
+        match self {
+            HttpRedirectError::TooLarge => Some("431 Request Header Fields Too Large"),
+            HttpRedirectError::InvalidRequest(_) | HttpRedirectError::MissingHost => {
+                Some("400 Bad Request")
+            }
+            HttpRedirectError::UnknownHost(_) => Some("421 Misdirected Request"),
+            HttpRedirectError::UnknownPath(_, _) => Some("404 Not Found"),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) async fn listen(
+    listener: Listener,
+    hosts: Arc<HostTable<Vec<HttpRoute>>>,
+    forwarded_headers: bool,
+) -> io::Result<()> {
+    loop {
+        let (mut inbound, address) = listener.accept_next().await?;
+        let connection = ActiveConnection::track();
+        debug!("Accepted new client {}", address);
+        let hosts_ref = Arc::clone(&hosts);
+
+        tokio::spawn(async move {
+            let _connection = connection;
+            let result = redirect(&mut inbound, address, hosts_ref, forwarded_headers).await;
+            if let Err(err) = result {
+                error!("http:{} {err}", address);
+
+                if let Some(status) = err.response() {
+                    if let Err(err) = respond(&mut inbound, status).await {
+                        error!("http:{} {err}", address);
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Only the first request of a connection is routed, so the connection is closed after its
+/// response for the client to send the next request on a new one. Upgraded connections, such as
+/// WebSocket, stay open.
+async fn redirect(
+    inbound: &mut Stream,
+    address: PeerAddress,
+    hosts: Arc<HostTable<Vec<HttpRoute>>>,
+    forwarded_headers: bool,
+) -> Result<(), HttpRedirectError> {
+    let mut buffer = Vec::new();
+    let head = timeout(
+        REQUEST_HEAD_TIMEOUT,
+        read_request_head(inbound, &mut buffer),
+    )
+    .await
+    .map_err(|_| HttpRedirectError::Timeout)??;
+
+    let hostname = head.hostname().ok_or(HttpRedirectError::MissingHost)?;
+    let routes = hosts
+        .get(hostname)
+        .ok_or_else(|| HttpRedirectError::UnknownHost(hostname.to_string()))?;
+    let route = routes
+        .iter()
+        .find(|route| route.accepts(&head.path))
+        .ok_or_else(|| HttpRedirectError::UnknownPath(hostname.to_string(), head.path.clone()))?;
+
+    let client = forwarded_headers.then_some(address);
+    let initial_bytes = head.rewrite(&buffer, client);
+
+    proxy_connection_with(
+        "http",
+        inbound,
+        address,
+        &route.target,
+        &route.outbound,
+        Some(&initial_bytes),
+        |inbound, outbound| Box::pin(relay_response_head(outbound, inbound)),
+    )
+    .await
+    .map_err(HttpRedirectError::ProxyError)
+}
+
+/// Reads from the client until the request head is complete, keeping every byte read to forward
+/// it to the backend.
+async fn read_request_head(
+    inbound: &mut Stream,
+    buffer: &mut Vec<u8>,
+) -> Result<RequestHead, HttpRedirectError> {
+    let mut chunk = [0; 4096];
+
+    loop {
+        let read = inbound
+            .read(&mut chunk)
+            .await
+            .map_err(HttpRedirectError::ReadFailed)?;
+        if read == 0 {
+            return Err(HttpRedirectError::ConnectionClosed);
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+
+        match parse_request_head(buffer) {
+            Ok(head) => return Ok(head),
+            Err(RequestError::Incomplete) if buffer.len() < MAX_REQUEST_HEAD_SIZE => {}
+            Err(RequestError::Incomplete) => return Err(HttpRedirectError::TooLarge),
+            Err(err) => return Err(HttpRedirectError::InvalidRequest(err)),
+        }
+    }
+}
+
+/// Forwards the response head of the backend with `Connection: close`, while the rest of the
+/// request goes to the backend. Responses which cannot be parsed are forwarded as is. Returns the
+/// bytes of the request sent to the backend and of the response received from it.
+async fn relay_response_head(
+    outbound: &mut Stream,
+    inbound: &mut Stream,
+) -> io::Result<(u64, u64)> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 4096];
+    let mut request_chunk = [0; 4096];
+    let mut request_ended = false;
+    let mut sent = 0;
+    let mut received = 0;
+
+    loop {
+        let read = tokio::select! {
+            read = outbound.read(&mut chunk) => read?,
+            read = inbound.read(&mut request_chunk), if !request_ended => {
+                match read? {
+                    0 => request_ended = true,
+                    read => {
+                        outbound.write_all(&request_chunk[..read]).await?;
+                        sent += read as u64;
+                    }
+                }
+                continue;
+            }
+        };
+        if read == 0 {
+            inbound.write_all(&buffer).await?;
+            return Ok((sent, received));
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        received += read as u64;
+
+        loop {
+            match close_response(&buffer) {
+                ResponseHead::Informational(length) => {
+                    inbound.write_all(&buffer[..length]).await?;
+                    buffer.drain(..length);
+                }
+                ResponseHead::Final(response) => {
+                    inbound.write_all(&response).await?;
+                    return Ok((sent, received));
+                }
+                ResponseHead::Incomplete if buffer.len() < MAX_RESPONSE_HEAD_SIZE => break,
+                ResponseHead::Incomplete => {
+                    inbound.write_all(&buffer).await?;
+                    return Ok((sent, received));
+                }
+            }
+        }
+    }
+}
+
+async fn respond(inbound: &mut Stream, status: &str) -> Result<(), HttpRedirectError> {
+    let body = format!("{status}\n");
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+
+    inbound
+        .write_all(response.as_bytes())
+        .await
+        .map_err(HttpRedirectError::WriteFailed)?;
+    inbound
+        .shutdown()
+        .await
+        .map_err(HttpRedirectError::WriteFailed)
+}
@@ -0,0 +1,82 @@
+use crate::backends::host_table::HostTable;
+use crate::backends::start_listeners;
+use crate::configuration::{HttpHost, ListenerConfig, OutboundConfig};
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+
+mod http_proxy;
+mod request;
+
+/// Backend of a hostname, only chosen for the paths under `path_prefix` if set.
+pub(crate) struct HttpRoute {
+    target: String,
+    path_prefix: Option<String>,
+    outbound: OutboundConfig,
+}
+
+impl HttpRoute {
+    fn accepts(&self, path: &str) -> bool {
+        let Some(prefix) = &self.path_prefix else {
+            return true;
+        };
+
+        // A prefix only matches whole path segments, "/api" matches "/api/users" but not "/apis"
+        match path.strip_prefix(prefix.as_str()) {
+            Some(rest) => {
+                prefix.ends_with('/')
+                    || rest.is_empty()
+                    || rest.starts_with('/')
+                    || rest.starts_with('?')
+            }
+            None => false,
+        }
+    }
+}
+
+pub(crate) fn start_http_proxy(
+    listener: ListenerConfig,
+    mut hosts: Vec<HttpHost>,
+    forwarded_headers: bool,
+) -> JoinHandle<()> {
+    // The longest path prefix of a hostname is tried first
+    hosts.sort_by_key(|host| {
+        std::cmp::Reverse(host.path_prefix.as_ref().map_or(0, |prefix| prefix.len()))
+    });
+    let routes = HostTable::grouped(hosts.into_iter().map(|mut host| {
+        host.outbound.inherit_socket(&listener.socket);
+        let route = HttpRoute {
+            target: host.target,
+            path_prefix: host.path_prefix,
+            outbound: host.outbound,
+        };
+        (host.hostname, route)
+    }));
+
+    let routes = Arc::new(routes);
+
+    start_listeners("HTTP", listener, move |socket| {
+        http_proxy::listen(socket, Arc::clone(&routes), forwarded_headers)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(path_prefix: &str) -> HttpRoute {
+        HttpRoute {
+            target: "127.0.0.1:80".to_string(),
+            path_prefix: Some(path_prefix.to_string()),
+            outbound: OutboundConfig::default(),
+        }
+    }
+
+    #[test]
+    fn should_match_whole_path_segments() {
+        assert!(route("/api").accepts("/api"));
+        assert!(route("/api").accepts("/api/users"));
+        assert!(route("/api").accepts("/api?page=2"));
+        assert!(!route("/api").accepts("/apis"));
+        assert!(route("/api/").accepts("/api/users"));
+    }
+}
@@ -0,0 +1,331 @@
+use std::net::IpAddr;
+use thiserror::Error;
+
+use crate::backends::stream::PeerAddress;
+
+const HEAD_END: &[u8] = b"\r\n\r\n";
+
+#[derive(Error, Debug, PartialEq)]
+pub(crate) enum RequestError {
+    #[error("the request head is incomplete")]
+    Incomplete,
+    #[error("the request head is malformed")]
+    Malformed,
+}
+
+/// Request line and headers of the first request of a connection.
+#[derive(Debug, PartialEq)]
+pub(crate) struct RequestHead {
+    pub(crate) path: String,
+    pub(crate) host: Option<String>,
+    /// Whether the client asks to switch protocols, such as to WebSocket.
+    upgrade: bool,
+    length: usize,
+}
+
+impl RequestHead {
+    /// Hostname of the `Host` header, without the port.
+    pub(crate) fn hostname(&self) -> Option<&str> {
+        let host = self.host.as_deref()?;
+
+        if host.starts_with('[') {
+            return host.split_inclusive(']').next();
+        }
+
+        match host.rsplit_once(':') {
+            Some((hostname, port)) if port.bytes().all(|byte| byte.is_ascii_digit()) => {
+                Some(hostname)
+            }
+            _ => Some(host),
+        }
+    }
+
+    /// Rewrites the head of `bytes` with `Connection: close`, as only the first request of a
+    /// connection is routed, and with the client appended to the `X-Forwarded-For` and
+    /// `Forwarded` headers if given, as `unknown` for Unix socket peers. Requests switching
+    /// protocols keep their connection headers. The body already read after the head is kept.
+    pub(crate) fn rewrite(&self, bytes: &[u8], client: Option<PeerAddress>) -> Vec<u8> {
+        // Keep the line ending of the last header
+        let head = &bytes[..self.length - 2];
+        let mut lines = head.split_inclusive(|byte| *byte == b'\n');
+        let mut forwarded_for = Vec::new();
+        let mut forwarded = Vec::new();
+        let mut rewritten = Vec::with_capacity(bytes.len() + 128);
+
+        if let Some(request_line) = lines.next() {
+            rewritten.extend_from_slice(request_line);
+        }
+
+        for line in lines {
+            match header(line) {
+                Some((name, value))
+                    if client.is_some() && name.eq_ignore_ascii_case("x-forwarded-for") =>
+                {
+                    forwarded_for.push(value);
+                }
+                Some((name, value))
+                    if client.is_some() && name.eq_ignore_ascii_case("forwarded") =>
+                {
+                    forwarded.push(value);
+                }
+                Some((name, _)) if !self.upgrade && is_connection_header(&name) => {}
+                _ => rewritten.extend_from_slice(line),
+            }
+        }
+
+        if let Some(client) = client {
+            let node = match client.ip() {
+                Some(IpAddr::V4(ip)) => ip.to_string(),
+                Some(IpAddr::V6(ip)) => format!("\"[{ip}]\""),
+                None => "unknown".to_string(),
+            };
+            forwarded_for.push(
+                client
+                    .ip()
+                    .map_or("unknown".to_string(), |ip| ip.to_string()),
+            );
+            let mut element = format!("for={node};proto=http");
+            if let Some(host) = &self.host {
+                element.push_str(&format!(";host=\"{host}\""));
+            }
+            forwarded.push(element);
+
+            rewritten.extend_from_slice(
+                format!(
+                    "X-Forwarded-For: {}\r\nForwarded: {}\r\n",
+                    forwarded_for.join(", "),
+                    forwarded.join(", ")
+                )
+                .as_bytes(),
+            );
+        }
+        if !self.upgrade {
+            rewritten.extend_from_slice(b"Connection: close\r\n");
+        }
+        rewritten.extend_from_slice(b"\r\n");
+        rewritten.extend_from_slice(&bytes[self.length..]);
+        rewritten
+    }
+}
+
+/// Outcome of reading the first bytes of a response.
+#[derive(Debug, PartialEq)]
+pub(crate) enum ResponseHead {
+    /// More bytes must be read.
+    Incomplete,
+    /// An informational response of this length, such as `100 Continue`, the final response
+    /// follows.
+    Informational(usize),
+    /// The final response, rewritten with `Connection: close` unless it switches protocols.
+    Final(Vec<u8>),
+}
+
+/// Rewrites the head of the response to a request rewritten by `RequestHead::rewrite`, for the
+/// client to send its next request on a new connection, routed again.
+pub(crate) fn close_response(bytes: &[u8]) -> ResponseHead {
+    let Some(length) = head_length(bytes) else {
+        return ResponseHead::Incomplete;
+    };
+    let status = bytes
+        .split(|byte| *byte == b' ')
+        .nth(1)
+        .and_then(|status| std::str::from_utf8(status).ok())
+        .and_then(|status| status.trim().parse::<u16>().ok());
+    match status {
+        Some(101) | None => return ResponseHead::Final(bytes.to_vec()),
+        Some(100..=199) => return ResponseHead::Informational(length),
+        Some(_) => {}
+    }
+
+    let head = &bytes[..length - 2];
+    let mut rewritten = Vec::with_capacity(bytes.len() + 32);
+    for (index, line) in head.split_inclusive(|byte| *byte == b'\n').enumerate() {
+        match header(line) {
+            Some((name, _)) if index > 0 && is_connection_header(&name) => {}
+            _ => rewritten.extend_from_slice(line),
+        }
+    }
+    rewritten.extend_from_slice(b"Connection: close\r\n\r\n");
+    rewritten.extend_from_slice(&bytes[length..]);
+    ResponseHead::Final(rewritten)
+}
+
+fn is_connection_header(name: &str) -> bool {
+    name.eq_ignore_ascii_case("connection") || name.eq_ignore_ascii_case("keep-alive")
+}
+
+fn head_length(bytes: &[u8]) -> Option<usize> {
+    bytes
+        .windows(HEAD_END.len())
+        .position(|window| window == HEAD_END)
+        .map(|position| position + HEAD_END.len())
+}
+
+/// Parses the request line and headers from the first bytes sent by the client. Returns
+/// `RequestError::Incomplete` when more bytes must be read.
+pub(crate) fn parse_request_head(bytes: &[u8]) -> Result<RequestHead, RequestError> {
+    let length = head_length(bytes).ok_or(RequestError::Incomplete)?;
+
+    let head = &bytes[..length - HEAD_END.len()];
+    let mut lines = head.split(|byte| *byte == b'\n');
+
+    let request_line = lines.next().ok_or(RequestError::Malformed)?;
+    let request_line = std::str::from_utf8(request_line).map_err(|_| RequestError::Malformed)?;
+    let mut parts = request_line.trim_end().split(' ');
+    let (Some(_method), Some(path), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(RequestError::Malformed);
+    };
+    if !version.starts_with("HTTP/") {
+        return Err(RequestError::Malformed);
+    }
+
+    let headers = lines.filter_map(header).collect::<Vec<_>>();
+    let host = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("host"))
+        .map(|(_, value)| value.clone());
+    let upgrade = headers.iter().any(|(name, value)| {
+        name.eq_ignore_ascii_case("connection")
+            && value
+                .split(',')
+                .any(|token| token.trim().eq_ignore_ascii_case("upgrade"))
+    });
+
+    Ok(RequestHead {
+        path: path.to_string(),
+        host,
+        upgrade,
+        length,
+    })
+}
+
+fn header(line: &[u8]) -> Option<(String, String)> {
+    let line = String::from_utf8_lossy(line);
+    let (name, value) = line.split_once(':')?;
+    Some((name.trim().to_string(), value.trim().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_path_and_host() {
+        // Given
+        let bytes = b"GET /api/users HTTP/1.1\r\nhost: Example.com:8080\r\nAccept: */*\r\n\r\n";
+
+        // When
+        let head = parse_request_head(bytes).unwrap();
+
+        // Then
+        assert_eq!(head.path, "/api/users");
+        assert_eq!(head.hostname(), Some("Example.com"));
+    }
+
+    #[test]
+    fn should_keep_ipv6_host() {
+        // Given
+        let bytes = b"GET / HTTP/1.1\r\nHost: [::1]:8080\r\n\r\n";
+
+        // When
+        let head = parse_request_head(bytes).unwrap();
+
+        // Then
+        assert_eq!(head.hostname(), Some("[::1]"));
+    }
+
+    #[test]
+    fn should_ask_for_more_bytes() {
+        // Given
+        let bytes = b"GET / HTTP/1.1\r\nHost: example.com\r\n";
+
+        // When
+        let result = parse_request_head(bytes);
+
+        // Then
+        assert_eq!(result, Err(RequestError::Incomplete));
+    }
+
+    #[test]
+    fn should_reject_other_protocols() {
+        // Given
+        let bytes = b"\x16\x03\x01\x00\x05hello\r\n\r\n";
+
+        // When
+        let result = parse_request_head(bytes);
+
+        // Then
+        assert_eq!(result, Err(RequestError::Malformed));
+    }
+
+    #[test]
+    fn should_append_client_to_forwarded_headers() {
+        // Given
+        let bytes =
+            b"POST / HTTP/1.1\r\nHost: example.com\r\nX-Forwarded-For: 10.0.0.1\r\n\r\nbody";
+        let head = parse_request_head(bytes).unwrap();
+
+        // When
+        let rewritten = head.rewrite(
+            bytes,
+            Some(PeerAddress::Inet("192.0.2.1:40000".parse().unwrap())),
+        );
+
+        // Then
+        assert_eq!(
+            String::from_utf8(rewritten).unwrap(),
+            "POST / HTTP/1.1\r\nHost: example.com\r\n\
+            X-Forwarded-For: 10.0.0.1, 192.0.2.1\r\n\
+            Forwarded: for=192.0.2.1;proto=http;host=\"example.com\"\r\n\
+            Connection: close\r\n\r\nbody"
+        );
+    }
+
+    #[test]
+    fn should_close_keep_alive_connections_but_not_upgrades() {
+        // Given
+        let keep_alive =
+            b"GET / HTTP/1.1\r\nHost: example.com\r\nConnection: keep-alive\r\nKeep-Alive: timeout=5\r\n\r\n";
+        let upgrade =
+            b"GET /ws HTTP/1.1\r\nHost: example.com\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\r\n";
+
+        // When
+        let keep_alive = parse_request_head(keep_alive)
+            .unwrap()
+            .rewrite(keep_alive, None);
+        let rewritten_upgrade = parse_request_head(upgrade).unwrap().rewrite(upgrade, None);
+
+        // Then
+        assert_eq!(
+            String::from_utf8(keep_alive).unwrap(),
+            "GET / HTTP/1.1\r\nHost: example.com\r\nConnection: close\r\n\r\n"
+        );
+        assert_eq!(rewritten_upgrade, upgrade);
+    }
+
+    #[test]
+    fn should_close_the_connection_after_the_final_response() {
+        // Given
+        let informational = b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\n";
+        let response = b"HTTP/1.1 200 OK\r\nConnection: keep-alive\r\nContent-Length: 2\r\n\r\nok";
+        let switching = b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\n\r\n";
+
+        // When / Then
+        assert_eq!(
+            close_response(informational),
+            ResponseHead::Informational(25)
+        );
+        assert_eq!(close_response(&response[..20]), ResponseHead::Incomplete);
+        assert_eq!(
+            close_response(response),
+            ResponseHead::Final(
+                b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok".to_vec()
+            )
+        );
+        assert_eq!(
+            close_response(switching),
+            ResponseHead::Final(switching.to_vec())
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Mutex;
+
+use socket2::{Socket, Type};
+use tracing::warn;
+
+/// Listening sockets passed by systemd or by the process this one took over, which no listener
+/// took yet.
+static INHERITED: Mutex<Vec<Socket>> = Mutex::new(Vec::new());
+
+pub(crate) fn inherit_listener(socket: Socket) {
+    INHERITED.lock().unwrap().push(socket);
+}
+
+/// Takes the inherited socket bound to `address`, `kind` telling TCP and UDP apart.
+pub(crate) fn take_inet_listener(address: SocketAddr, kind: Type) -> Option<Socket> {
+    take_listener(|socket| {
+        socket.r#type().is_ok_and(|socket_kind| socket_kind == kind)
+            && socket
+                .local_addr()
+                .is_ok_and(|local| local.as_socket() == Some(address))
+    })
+}
+
+/// Takes the inherited socket bound to the Unix socket `path`.
+pub(crate) fn take_unix_listener(path: &str) -> Option<Socket> {
+    take_listener(|socket| {
+        socket
+            .local_addr()
+            .is_ok_and(|local| local.as_pathname() == Some(Path::new(path)))
+    })
+}
+
+fn take_listener(matches: impl Fn(&Socket) -> bool) -> Option<Socket> {
+    let mut sockets = INHERITED.lock().unwrap();
+    let index = sockets.iter().position(matches)?;
+    Some(sockets.swap_remove(index))
+}
+
+/// Closes the inherited sockets of addresses no longer in the configuration.
+pub(crate) fn close_unused_listeners() {
+    for socket in INHERITED.lock().unwrap().drain(..) {
+        match socket.local_addr() {
+            Ok(address) => {
+                warn!("Closing the inherited socket of {address:?}, not in the configuration")
+            }
+            Err(err) => warn!("Closing an inherited socket; error={err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn should_take_the_socket_bound_to_the_address() {
+        // Given
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        inherit_listener(Socket::from(listener));
+
+        // When
+        let udp = take_inet_listener(address, Type::DGRAM);
+        let tcp = take_inet_listener(address, Type::STREAM);
+
+        // Then
+        assert!(udp.is_none());
+        assert_eq!(
+            tcp.unwrap().local_addr().unwrap().as_socket(),
+            Some(address)
+        );
+        assert!(take_inet_listener(address, Type::STREAM).is_none());
+    }
+}
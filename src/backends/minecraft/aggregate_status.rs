@@ -0,0 +1,143 @@
+use crate::backends::minecraft::status::{fetch_status, StatusError};
+use crate::configuration::AggregateStatusConfig;
+use serde_json::{json, Value};
+use tracing::debug;
+
+/// Maximum number of players listed in the merged sample, same as vanilla.
+const SAMPLE_LIMIT: usize = 12;
+
+/// Status of a route summing the players of several backends.
+pub(crate) struct AggregateStatus {
+    targets: Vec<String>,
+}
+
+impl AggregateStatus {
+    /// The backend of the route comes first so its version and description are used.
+    pub(crate) fn new(
+        target: &str,
+        config: AggregateStatusConfig,
+        server_targets: &[String],
+    ) -> Self {
+        let other_targets = if config.targets.is_empty() {
+            server_targets.to_vec()
+        } else {
+            config.targets
+        };
+
+        let mut targets = vec![target.to_string()];
+        for other_target in other_targets {
+            if !targets.contains(&other_target) {
+                targets.push(other_target);
+            }
+        }
+
+        Self { targets }
+    }
+
+    pub(crate) async fn fetch(
+        &self,
+        hostname: &str,
+        port: u16,
+        protocol: i32,
+    ) -> Result<Value, StatusError> {
+        let responses = futures::future::join_all(
+            self.targets
+                .iter()
+                .map(|target| fetch_status(target, hostname, port, protocol)),
+        )
+        .await;
+
+        let statuses = self
+            .targets
+            .iter()
+            .zip(responses)
+            .filter_map(|(target, response)| match response {
+                Ok(status) => Some(status),
+                Err(err) => {
+                    debug!("could not query status of {target}; error={err}");
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        merge_statuses(statuses).ok_or(StatusError::NoResponse)
+    }
+}
+
+/// Uses the first status as a base and replaces its players with the sum of all statuses.
+fn merge_statuses(statuses: Vec<Value>) -> Option<Value> {
+    let mut statuses = statuses.into_iter();
+    let mut merged = statuses.next()?;
+
+    let mut online = merged["players"]["online"].as_i64().unwrap_or(0);
+    let mut max = merged["players"]["max"].as_i64().unwrap_or(0);
+    let mut sample = players_sample(&merged);
+
+    for status in statuses {
+        online += status["players"]["online"].as_i64().unwrap_or(0);
+        max += status["players"]["max"].as_i64().unwrap_or(0);
+
+        for player in players_sample(&status) {
+            if !sample.iter().any(|known| known["id"] == player["id"]) {
+                sample.push(player);
+            }
+        }
+    }
+
+    sample.truncate(SAMPLE_LIMIT);
+
+    let mut players = json!({ "online": online, "max": max });
+    if !sample.is_empty() {
+        players["sample"] = Value::Array(sample);
+    }
+    merged["players"] = players;
+
+    Some(merged)
+}
+
+fn players_sample(status: &Value) -> Vec<Value> {
+    status["players"]["sample"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_sum_players() {
+        // Given
+        let lobby = json!({
+            "version": { "name": "1.21", "protocol": 767 },
+            "players": { "max": 20, "online": 2, "sample": [
+                { "name": "Notch", "id": "069a79f4-44e9-4e26-b06d-5a7e7c3b367f" },
+                { "name": "jeb_", "id": "853c80ef-3c37-49fd-aa49-938b674adae6" },
+            ] },
+            "description": { "text": "Lobby" },
+        });
+        let survival = json!({
+            "version": { "name": "1.20", "protocol": 763 },
+            "players": { "max": 50, "online": 1, "sample": [
+                { "name": "Notch", "id": "069a79f4-44e9-4e26-b06d-5a7e7c3b367f" },
+            ] },
+            "description": { "text": "Survival" },
+        });
+
+        // When
+        let merged = merge_statuses(vec![lobby, survival]).unwrap();
+
+        // Then
+        assert_eq!(merged["version"]["protocol"], 767);
+        assert_eq!(merged["description"]["text"], "Lobby");
+        assert_eq!(merged["players"]["online"], 3);
+        assert_eq!(merged["players"]["max"], 70);
+        assert_eq!(merged["players"]["sample"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn should_not_merge_without_status() {
+        assert_eq!(merge_statuses(vec![]), None);
+    }
+}
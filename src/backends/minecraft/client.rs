@@ -1,20 +1,30 @@
+use crate::backends::host_table::HostTable;
 use crate::backends::minecraft::payload::{Payload, PayloadAppendError};
+use crate::backends::minecraft::protocol::packets::login::{
+    handle_login_start, login_disconnect, LoginStart,
+};
+use crate::backends::minecraft::protocol::packets::status::{pong_response, status_response};
 use crate::backends::minecraft::protocol::parse_packet::{parse_minecraft_packet, Packet};
 use crate::backends::minecraft::protocol::state::State;
+use crate::backends::minecraft::route::Route;
 use crate::backends::proxy_connection::{proxy_connection, ProxyConnectionError};
-use std::collections::HashMap;
-use std::net::SocketAddr;
+use crate::backends::stream::{PeerAddress, Stream};
 use std::sync::Arc;
 use thiserror::Error;
-use tokio::io::AsyncReadExt;
-use tokio::net::TcpStream;
-use tracing::{debug, error, trace};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::{debug, info, trace};
+
+struct HandshakeInfo {
+    hostname: String,
+    port: u16,
+    protocol: i32,
+}
 
 pub(crate) struct Client {
-    socket: TcpStream,
+    socket: Stream,
     state: State,
     payload: Payload,
-    address: SocketAddr,
+    address: PeerAddress,
 }
 
 #[derive(Error, Debug)]
@@ -35,12 +45,16 @@ pub(crate) enum RedirectError {
     CouldNotParsePacket(Box<dyn std::error::Error>),
     #[error("client trying to connect to unknown host {0}")]
     UnknownHost(String),
+    #[error("failed to read packet; error={0}")]
+    ReadError(ClientReadError),
+    #[error("failed to write to client; error={0}")]
+    WriteFailed(std::io::Error),
     #[error("{0}")]
     ProxyError(ProxyConnectionError),
 }
 
 impl Client {
-    pub(crate) fn new(socket: TcpStream, address: SocketAddr) -> Client {
+    pub(crate) fn new(socket: Stream, address: PeerAddress) -> Client {
         Client {
             socket,
             address,
@@ -96,18 +110,61 @@ impl Client {
 
     pub(crate) async fn redirect_trafic(
         &mut self,
-        hosts_ref: Arc<HashMap<String, String>>,
+        hosts_ref: Arc<HostTable<Route>>,
     ) -> Result<(), RedirectError> {
-        let hostname = self.get_hostname_from_payload()?;
+        let HandshakeInfo {
+            hostname,
+            port,
+            protocol,
+        } = self.get_handshake_from_payload()?;
         let host = hosts_ref.get(&hostname);
 
-        if let Some(server_addr) = host {
+        if let Some(route) = host {
+            let mut initial_bytes = self.payload.get_all_bytes().to_vec();
+            let ip = self.address.ip();
+
+            match self.state {
+                State::Status => {
+                    if let Some(status) = route.proxied_status(ip, &hostname, port, protocol).await
+                    {
+                        return self.answer_status(&status).await;
+                    }
+                }
+                State::Login
+                    if route.access.is_enabled() || route.maintenance.applies_to_address(ip) =>
+                {
+                    let handshake = self.payload.get_packet_bytes().to_vec();
+                    let player = self.read_login_start(protocol).await?;
+
+                    if route.maintenance.applies_to_player(ip, &player.name) {
+                        info!(
+                            "minecraft:login of {} from {} denied; reason=maintenance",
+                            player.name, self.address,
+                        );
+                        let kick_message = route.maintenance.kick_message().to_string();
+                        return self.disconnect(&kick_message).await;
+                    }
+
+                    if let Err(denied) = route.access.check(&player.name, player.uuid.as_deref()) {
+                        info!(
+                            "minecraft:login of {} from {} denied; reason={:?}",
+                            player.name, self.address, denied,
+                        );
+                        return self.disconnect(&denied.to_string()).await;
+                    }
+
+                    initial_bytes = [handshake.as_slice(), self.payload.get_all_bytes()].concat();
+                }
+                _ => {}
+            }
+
             proxy_connection(
                 "minecraft",
                 &mut self.socket,
                 self.address,
-                server_addr,
-                Some(self.payload.get_all_bytes()),
+                &route.target,
+                &route.outbound,
+                Some(&initial_bytes),
             )
             .await
             .map_err(RedirectError::ProxyError)?;
@@ -118,7 +175,93 @@ impl Client {
         Ok(())
     }
 
-    fn get_hostname_from_payload(&mut self) -> Result<String, RedirectError> {
+    /// Replaces the payload with the next packet, which may already be partially received.
+    async fn read_next_packet(&mut self) -> Result<(), ClientReadError> {
+        let mut payload = Payload::new();
+        let extra_bytes = self.payload.get_extra_bytes();
+        if !extra_bytes.is_empty() {
+            payload
+                .append_bytes(extra_bytes, extra_bytes.len())
+                .map_err(ClientReadError::InvalidPacket)?;
+        }
+
+        while !payload.is_complete() {
+            let mut buf = vec![0; payload.get_remaining_to_read()];
+            let bytes_received = self
+                .socket
+                .read(&mut buf)
+                .await
+                .map_err(ClientReadError::FailedToRead)?;
+
+            if bytes_received == 0 {
+                return Err(ClientReadError::NoBytesReceived);
+            }
+
+            payload
+                .append_bytes(&buf[..bytes_received], bytes_received)
+                .map_err(ClientReadError::InvalidPacket)?;
+        }
+
+        self.payload = payload;
+        Ok(())
+    }
+
+    async fn read_login_start(&mut self, protocol: i32) -> Result<LoginStart, RedirectError> {
+        self.read_next_packet()
+            .await
+            .map_err(RedirectError::ReadError)?;
+
+        let bytes = self.payload.get_data();
+        if bytes.first() != Some(&0x00) {
+            return Err(RedirectError::CouldNotParsePacket(
+                "expected a login start packet".into(),
+            ));
+        }
+
+        let mut index = 1;
+        let player = handle_login_start(bytes, &mut index, protocol)
+            .map_err(RedirectError::CouldNotParsePacket)?;
+        debug!("Received login start from {}", player.name);
+
+        Ok(player)
+    }
+
+    /// Answers the Status Request and the Ping Request in place of the backend.
+    async fn answer_status(&mut self, response: &str) -> Result<(), RedirectError> {
+        self.read_next_packet()
+            .await
+            .map_err(RedirectError::ReadError)?;
+        self.socket
+            .write_all(&status_response(response))
+            .await
+            .map_err(RedirectError::WriteFailed)?;
+
+        // Clients may close the connection without sending a ping
+        if self.read_next_packet().await.is_ok() && self.payload.get_data().first() == Some(&0x01) {
+            let payload = &self.payload.get_data()[1..self.payload.get_packet_size()];
+            self.socket
+                .write_all(&pong_response(payload))
+                .await
+                .map_err(RedirectError::WriteFailed)?;
+        }
+
+        Ok(())
+    }
+
+    async fn disconnect(&mut self, reason: &str) -> Result<(), RedirectError> {
+        let reason = serde_json::json!({ "text": reason }).to_string();
+
+        self.socket
+            .write_all(&login_disconnect(&reason))
+            .await
+            .map_err(RedirectError::WriteFailed)?;
+        self.socket
+            .shutdown()
+            .await
+            .map_err(RedirectError::WriteFailed)
+    }
+
+    fn get_handshake_from_payload(&mut self) -> Result<HandshakeInfo, RedirectError> {
         let bytes = self.payload.get_data();
         let length = self.payload.get_packet_size();
         trace!(
@@ -133,12 +276,17 @@ impl Client {
 
                 match packet {
                     Packet::Handshake {
+                        protocol,
                         hostname,
+                        port,
                         next_state,
-                        ..
                     } => {
                         self.update_state(next_state);
-                        Ok(hostname)
+                        Ok(HandshakeInfo {
+                            hostname,
+                            port,
+                            protocol,
+                        })
                     }
                 }
             }
@@ -0,0 +1,106 @@
+use crate::backends::minecraft::status::simple_status;
+use crate::configuration::MaintenanceConfig;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+const DEFAULT_MOTD: &str = "Server under maintenance";
+const DEFAULT_VERSION: &str = "Maintenance";
+const DEFAULT_KICK_MESSAGE: &str = "Server is currently under maintenance, please try again later.";
+
+/// Maintenance state of a route, the flag is shared with the control socket to be toggled at
+/// runtime.
+pub(crate) struct Maintenance {
+    enabled: Arc<AtomicBool>,
+    config: MaintenanceConfig,
+}
+
+impl Maintenance {
+    pub(crate) fn new(config: Option<MaintenanceConfig>) -> Self {
+        let config = config.unwrap_or_default();
+
+        Self {
+            enabled: Arc::new(AtomicBool::new(config.enabled)),
+            config,
+        }
+    }
+
+    pub(crate) fn flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.enabled)
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Whether the maintenance applies to a client, before knowing the name of the player. Clients
+    /// of a Unix socket have no address to bypass it with.
+    pub(crate) fn applies_to_address(&self, address: Option<IpAddr>) -> bool {
+        self.is_enabled()
+            && !address.is_some_and(|address| {
+                self.config
+                    .bypass
+                    .iter()
+                    .any(|entry| entry.parse::<IpAddr>() == Ok(address))
+            })
+    }
+
+    pub(crate) fn applies_to_player(&self, address: Option<IpAddr>, name: &str) -> bool {
+        self.applies_to_address(address)
+            && !self
+                .config
+                .bypass
+                .iter()
+                .any(|entry| entry.eq_ignore_ascii_case(name))
+    }
+
+    pub(crate) fn kick_message(&self) -> &str {
+        self.config
+            .kick_message
+            .as_deref()
+            .unwrap_or(DEFAULT_KICK_MESSAGE)
+    }
+
+    /// JSON status response shown in the server list.
+    pub(crate) fn status(&self) -> String {
+        simple_status(
+            self.config.version.as_deref().unwrap_or(DEFAULT_VERSION),
+            self.config.motd.as_deref().unwrap_or(DEFAULT_MOTD),
+        )
+        .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_let_bypass_list_through() {
+        // Given
+        let maintenance = Maintenance::new(Some(MaintenanceConfig {
+            enabled: true,
+            bypass: vec!["127.0.0.1".to_string(), "Notch".to_string()],
+            ..Default::default()
+        }));
+        let localhost = "127.0.0.1".parse().ok();
+        let remote = "192.168.1.2".parse().ok();
+
+        // When / Then
+        assert!(!maintenance.applies_to_address(localhost));
+        assert!(maintenance.applies_to_address(remote));
+        assert!(maintenance.applies_to_address(None));
+        assert!(!maintenance.applies_to_player(remote, "notch"));
+        assert!(!maintenance.applies_to_player(None, "notch"));
+        assert!(maintenance.applies_to_player(remote, "jeb_"));
+    }
+
+    #[test]
+    fn should_not_apply_when_disabled() {
+        // Given
+        let maintenance = Maintenance::new(None);
+
+        // When / Then
+        assert!(!maintenance.applies_to_address("192.168.1.2".parse().ok()));
+    }
+}
@@ -1,24 +1,23 @@
-use std::collections::HashMap;
-use std::error::Error;
+use std::io;
 use std::sync::Arc;
-use tokio::net::TcpListener;
-use tracing::{debug, error, info};
+use tracing::{debug, error};
 
+use crate::backends::host_table::HostTable;
 use crate::backends::minecraft::client::Client;
+use crate::backends::minecraft::route::Route;
+use crate::backends::stream::Listener;
+use crate::backends::supervisor::ActiveConnection;
 
-pub(crate) async fn listen(
-    addr: String,
-    hosts: Arc<HashMap<String, String>>,
-) -> Result<(), Box<dyn Error>> {
-    let listener = TcpListener::bind(&addr).await?;
-    info!("Listening on: {}", addr);
-
-    while let Ok((inbound, address)) = listener.accept().await {
-        debug!("Accepted new client {}:{}", address.ip(), address.port());
+pub(crate) async fn listen(listener: Listener, hosts: Arc<HostTable<Route>>) -> io::Result<()> {
+    loop {
+        let (inbound, address) = listener.accept_next().await?;
+        let connection = ActiveConnection::track();
+        debug!("Accepted new client {}", address);
         let mut client = Client::new(inbound, address);
         let hosts_ref = Arc::clone(&hosts);
 
         tokio::spawn(async move {
+            let _connection = connection;
             loop {
                 if let Err(err) = client.read_socket().await {
                     error!("{err}");
@@ -36,6 +35,4 @@ pub(crate) async fn listen(
             }
         });
     }
-
-    Ok(())
 }
@@ -1,24 +1,91 @@
-use crate::configuration::Host;
-use std::collections::HashMap;
+use crate::backends::host_table::HostTable;
+use crate::backends::minecraft::query::QueryResponder;
+use crate::backends::minecraft::route::Route;
+use crate::backends::start_listeners;
+use crate::configuration::{Host, ListenAddresses, ListenerConfig, QueryConfig};
+use crate::control::Controls;
 use std::sync::Arc;
+use tokio::net::UdpSocket;
 use tokio::task::JoinHandle;
-use tracing::error;
 
+mod aggregate_status;
 mod client;
+mod maintenance;
 mod minecraft_proxy;
 mod payload;
+mod player_lists;
 mod protocol;
+mod query;
+mod route;
+mod status;
+mod status_cache;
+mod status_override;
 
-pub(crate) fn start_minecraft_proxy(addr: String, hosts: Vec<Host>) -> JoinHandle<()> {
-    let hosts = hosts
+pub(crate) use player_lists::{reload_player_lists, AccessControl};
+
+pub(crate) fn start_minecraft_proxy(
+    listener: ListenerConfig,
+    hosts: Vec<Host>,
+    query: Option<QueryConfig>,
+    global_access: &AccessControl,
+    controls: &mut Controls,
+) -> JoinHandle<()> {
+    // Query clients do not send a hostname, the first host answers unless configured
+    let query = query.map(|query| {
+        let hostname = query
+            .hostname
+            .or_else(|| hosts.first().map(|host| host.hostname.clone()))
+            .unwrap_or_default();
+        (query.listen, hostname)
+    });
+
+    let server_targets = hosts
+        .iter()
+        .map(|host| host.target.clone())
+        .collect::<Vec<_>>();
+
+    let routes = hosts
         .into_iter()
-        .map(|host| (host.hostname, host.target))
-        .collect::<HashMap<String, String>>();
+        .map(|mut host| {
+            host.outbound.inherit_socket(&listener.socket);
+            let hostname = host.hostname.clone();
+            let route = Route::new(host, global_access, &server_targets);
+            controls.register_maintenance(&hostname, route.maintenance.flag());
+            (hostname, route)
+        })
+        .collect::<HostTable<Route>>();
+
+    let routes = Arc::new(routes);
+
+    let query = query.map(|(query_listen, hostname)| {
+        let host = listener
+            .listen
+            .0
+            .first()
+            .and_then(|address| address.parse().ok())
+            .unwrap_or_else(|| ([0, 0, 0, 0], 25565).into());
+        let responder = Arc::new(QueryResponder::new(Arc::clone(&routes), hostname, host));
+        // Supervised and handed over like the listeners of the block
+        let query_listener = ListenerConfig {
+            name: listener.name.as_ref().map(|name| format!("{name} query")),
+            listen: ListenAddresses(vec![query_listen]),
+            acceptors: 1,
+            ..listener.clone()
+        };
+        start_listeners(
+            "Minecraft query",
+            query_listener,
+            move |socket: UdpSocket| Arc::clone(&responder).listen(socket),
+        )
+    });
 
+    let minecraft = start_listeners("Minecraft", listener, move |socket| {
+        minecraft_proxy::listen(socket, Arc::clone(&routes))
+    });
     tokio::spawn(async move {
-        let proxy = minecraft_proxy::listen(addr, Arc::new(hosts)).await;
-        if let Err(err) = proxy {
-            error!("error with Minecraft proxy; error={err}");
+        let _ = minecraft.await;
+        if let Some(query) = query {
+            let _ = query.await;
         }
     })
 }
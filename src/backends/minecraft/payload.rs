@@ -83,6 +83,22 @@ impl Payload {
         &self.bytes
     }
 
+    /// Bytes of the packet, without the bytes of the following packets read at the same time.
+    pub(crate) fn get_packet_bytes(&self) -> &[u8] {
+        match self.expected_length {
+            None => &self.bytes,
+            Some(expected_length) => &self.bytes[..expected_length.min(self.bytes.len())],
+        }
+    }
+
+    /// Bytes received after the end of the packet, which belong to the next packet.
+    pub(crate) fn get_extra_bytes(&self) -> &[u8] {
+        match self.expected_length {
+            None => &[],
+            Some(expected_length) => &self.bytes[expected_length.min(self.bytes.len())..],
+        }
+    }
+
     pub(crate) fn get_packet_size(&self) -> usize {
         match self.expected_length {
             None => 0,
@@ -289,4 +305,19 @@ mod tests {
         }
         assert_eq!(payload.get_packet_size(), 16);
     }
+
+    #[test]
+    fn test_read_payload_followed_by_next_packet() {
+        // Given
+        let mut payload = Payload::new();
+        let bytes = vec![0x01, 0x00, 0x02, 0x00, 0x01];
+
+        // When
+        payload.append_bytes(&bytes, 5).unwrap();
+
+        // Then
+        assert!(payload.is_complete());
+        assert_eq!(payload.get_packet_bytes(), vec![0x01, 0x00]);
+        assert_eq!(payload.get_extra_bytes(), vec![0x02, 0x00, 0x01]);
+    }
 }
@@ -0,0 +1,397 @@
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{fmt, fs};
+use tracing::{error, info};
+
+/// Entry of a vanilla `whitelist.json` file.
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct WhitelistEntry {
+    uuid: Option<String>,
+    name: Option<String>,
+}
+
+/// Entry of a vanilla `banned-players.json` file.
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct BannedPlayerEntry {
+    uuid: Option<String>,
+    name: Option<String>,
+    reason: Option<String>,
+    expires: Option<String>,
+}
+
+impl WhitelistEntry {
+    /// The name is required to match: the UUID is sent by the client in its Login Start packet,
+    /// and the UUIDs of whitelisted players are public. A UUID only narrows down the entry.
+    fn allows(&self, name: &str, uuid: Option<&str>) -> bool {
+        let name_matches = self
+            .name
+            .as_deref()
+            .is_some_and(|entry_name| entry_name.eq_ignore_ascii_case(name));
+        let uuid_matches = match (self.uuid.as_deref(), uuid) {
+            (Some(entry_uuid), Some(uuid)) => entry_uuid.eq_ignore_ascii_case(uuid),
+            _ => true,
+        };
+
+        name_matches && uuid_matches
+    }
+}
+
+impl BannedPlayerEntry {
+    /// Either the name or the UUID bans a player, trusting the UUID of the client is safe for
+    /// bans as it can only get the client denied.
+    fn matches(&self, name: &str, uuid: Option<&str>) -> bool {
+        let uuid_matches = match (self.uuid.as_deref(), uuid) {
+            (Some(entry_uuid), Some(uuid)) => entry_uuid.eq_ignore_ascii_case(uuid),
+            _ => false,
+        };
+        let name_matches = self
+            .name
+            .as_deref()
+            .is_some_and(|entry_name| entry_name.eq_ignore_ascii_case(name));
+
+        uuid_matches || name_matches
+    }
+
+    fn is_active(&self, now: i64) -> bool {
+        match self.expires.as_deref() {
+            None | Some("forever") => true,
+            Some(expires) => parse_vanilla_date(expires).is_none_or(|expires| expires > now),
+        }
+    }
+}
+
+/// Every player list of the proxy, to reload them all at once.
+static PLAYER_LISTS: Mutex<Vec<Weak<dyn Reload>>> = Mutex::new(Vec::new());
+
+trait Reload: Send + Sync {
+    fn reload(&self);
+}
+
+/// Reads again the player lists modified since they were last read, rather than when a player
+/// next logs in, so errors in the files are reported right away.
+pub(crate) fn reload_player_lists() {
+    let lists = PLAYER_LISTS.lock().unwrap().clone();
+    for list in lists.iter().filter_map(Weak::upgrade) {
+        list.reload();
+    }
+}
+
+/// A player list file, reloaded whenever its modification time changes.
+pub(crate) struct PlayerList<T> {
+    path: PathBuf,
+    state: RwLock<PlayerListState<T>>,
+}
+
+struct PlayerListState<T> {
+    modified: Option<SystemTime>,
+    entries: Vec<T>,
+}
+
+impl<T: DeserializeOwned + Clone> PlayerList<T> {
+    pub(crate) fn new(path: impl Into<PathBuf>) -> Self {
+        let list = Self {
+            path: path.into(),
+            state: RwLock::new(PlayerListState {
+                modified: None,
+                entries: Vec::new(),
+            }),
+        };
+        list.reload_if_modified();
+        list
+    }
+
+    /// Loads the list, keeping track of it for `reload_player_lists`.
+    fn shared(path: &str) -> Arc<Self>
+    where
+        T: Send + Sync + 'static,
+    {
+        let list = Arc::new(Self::new(path));
+        let reload: Arc<dyn Reload> = list.clone();
+        PLAYER_LISTS.lock().unwrap().push(Arc::downgrade(&reload));
+        list
+    }
+
+    fn reload_if_modified(&self) {
+        let modified = match fs::metadata(&self.path).and_then(|metadata| metadata.modified()) {
+            Ok(modified) => modified,
+            Err(err) => {
+                error!(
+                    "cannot read player list {}; error={err}",
+                    self.path.display()
+                );
+                return;
+            }
+        };
+
+        if self.state.read().unwrap().modified == Some(modified) {
+            return;
+        }
+
+        let entries = fs::read_to_string(&self.path)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| {
+                serde_json::from_str::<Vec<T>>(&contents).map_err(|err| err.to_string())
+            });
+
+        let mut state = self.state.write().unwrap();
+        state.modified = Some(modified);
+        match entries {
+            Ok(entries) => {
+                info!(
+                    "Loaded {} entries from {}",
+                    entries.len(),
+                    self.path.display()
+                );
+                state.entries = entries;
+            }
+            // Keep the previous entries so an invalid edit does not lift every ban
+            Err(err) => error!(
+                "cannot parse player list {}; error={err}",
+                self.path.display()
+            ),
+        }
+    }
+
+    fn entries(&self) -> Vec<T> {
+        self.reload_if_modified();
+        self.state.read().unwrap().entries.clone()
+    }
+}
+
+impl<T: DeserializeOwned + Clone + Send + Sync> Reload for PlayerList<T> {
+    fn reload(&self) {
+        self.reload_if_modified();
+    }
+}
+
+pub(crate) type Whitelist = PlayerList<WhitelistEntry>;
+pub(crate) type BanList = PlayerList<BannedPlayerEntry>;
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum AccessDenied {
+    NotWhitelisted,
+    Banned {
+        reason: Option<String>,
+        expires: Option<String>,
+    },
+}
+
+impl Display for AccessDenied {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessDenied::NotWhitelisted => f.write_str("You are not white-listed on this server!"),
+            AccessDenied::Banned { reason, expires } => {
+                f.write_str("You are banned from this server.")?;
+                if let Some(reason) = reason {
+                    write!(f, "\nReason: {reason}")?;
+                }
+                if let Some(expires) = expires {
+                    write!(f, "\nYour ban will be removed on {expires}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Whitelists and ban lists applying to a route, global lists included.
+#[derive(Default, Clone)]
+pub(crate) struct AccessControl {
+    whitelists: Vec<Arc<Whitelist>>,
+    ban_lists: Vec<Arc<BanList>>,
+}
+
+impl AccessControl {
+    pub(crate) fn from_files(whitelist: Option<&String>, banned_players: Option<&String>) -> Self {
+        Self {
+            whitelists: whitelist
+                .map(|path| Whitelist::shared(path))
+                .into_iter()
+                .collect(),
+            ban_lists: banned_players
+                .map(|path| BanList::shared(path))
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    /// Combines the lists of both access controls, used to apply the global lists to a route.
+    pub(crate) fn merge(&self, other: &AccessControl) -> Self {
+        Self {
+            whitelists: [self.whitelists.clone(), other.whitelists.clone()].concat(),
+            ban_lists: [self.ban_lists.clone(), other.ban_lists.clone()].concat(),
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        !self.whitelists.is_empty() || !self.ban_lists.is_empty()
+    }
+
+    /// A player is allowed if it is not banned in any list and is present in at least one of
+    /// the whitelists, if any.
+    pub(crate) fn check(&self, name: &str, uuid: Option<&str>) -> Result<(), AccessDenied> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs() as i64);
+
+        let ban = self
+            .ban_lists
+            .iter()
+            .flat_map(|list| list.entries())
+            .find(|entry| entry.matches(name, uuid) && entry.is_active(now));
+
+        if let Some(ban) = ban {
+            return Err(AccessDenied::Banned {
+                reason: ban.reason,
+                expires: ban.expires.filter(|expires| expires != "forever"),
+            });
+        }
+
+        let whitelisted = self.whitelists.is_empty()
+            || self
+                .whitelists
+                .iter()
+                .any(|list| list.entries().iter().any(|entry| entry.allows(name, uuid)));
+
+        if whitelisted {
+            Ok(())
+        } else {
+            Err(AccessDenied::NotWhitelisted)
+        }
+    }
+}
+
+/// Parses dates formatted as `2024-05-01 10:00:00 +0000` into a unix timestamp.
+fn parse_vanilla_date(date: &str) -> Option<i64> {
+    let mut parts = date.split_whitespace();
+    let mut day = parts.next()?.split('-').map(|part| part.parse::<i64>());
+    let mut time = parts.next()?.split(':').map(|part| part.parse::<i64>());
+    let offset = parts.next().unwrap_or("+0000");
+
+    let (year, month, day) = (day.next()?.ok()?, day.next()?.ok()?, day.next()?.ok()?);
+    let (hours, minutes, seconds) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+
+    let offset_sign = if offset.starts_with('-') { -1 } else { 1 };
+    let offset = offset.trim_start_matches(['+', '-']);
+    let offset_hours = offset.get(0..2)?.parse::<i64>().ok()?;
+    let offset_minutes = offset.get(2..4)?.parse::<i64>().ok()?;
+
+    let local = days_from_civil(year, month, day) * 86_400 + hours * 3_600 + minutes * 60 + seconds;
+    Some(local - offset_sign * (offset_hours * 3_600 + offset_minutes * 60))
+}
+
+/// Number of days since 1970-01-01 in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn write_list(name: &str, contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!("proxy-{}-{name}", std::process::id()));
+        let mut file = fs::File::create(&path).unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn should_parse_vanilla_date() {
+        assert_eq!(parse_vanilla_date("1970-01-01 00:00:00 +0000"), Some(0));
+        assert_eq!(
+            parse_vanilla_date("2024-05-01 10:00:00 +0000"),
+            Some(1_714_557_600)
+        );
+        assert_eq!(
+            parse_vanilla_date("2024-05-01 12:00:00 +0200"),
+            Some(1_714_557_600)
+        );
+        assert_eq!(parse_vanilla_date("forever"), None);
+    }
+
+    #[test]
+    fn should_deny_banned_player() {
+        // Given
+        let path = write_list(
+            "banned-players.json",
+            r#"[
+                {"uuid": "069a79f4-44e9-4e26-b06d-5a7e7c3b367f", "name": "Notch", "created": "2024-01-01 00:00:00 +0000", "source": "Server", "expires": "forever", "reason": "Griefing"},
+                {"uuid": "853c80ef-3c37-49fd-aa49-938b674adae6", "name": "jeb_", "created": "2024-01-01 00:00:00 +0000", "source": "Server", "expires": "2000-01-01 00:00:00 +0000", "reason": "Expired"}
+            ]"#,
+        );
+        let access = AccessControl::from_files(None, Some(&path));
+
+        // When / Then
+        assert_eq!(
+            access.check("someone", Some("069A79F4-44E9-4E26-B06D-5A7E7C3B367F")),
+            Err(AccessDenied::Banned {
+                reason: Some("Griefing".to_string()),
+                expires: None,
+            })
+        );
+        assert!(access.check("jeb_", None).is_ok());
+        assert!(access.check("Dinnerbone", None).is_ok());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_allow_player_in_any_whitelist() {
+        // Given
+        let global = write_list(
+            "global-whitelist.json",
+            r#"[{"uuid": null, "name": "Notch"}]"#,
+        );
+        let route = write_list("route-whitelist.json", r#"[{"name": "jeb_"}]"#);
+        let access = AccessControl::from_files(Some(&global), None)
+            .merge(&AccessControl::from_files(Some(&route), None));
+
+        // When / Then
+        assert!(access.check("notch", None).is_ok());
+        assert!(access.check("jeb_", None).is_ok());
+        assert_eq!(
+            access.check("Dinnerbone", None),
+            Err(AccessDenied::NotWhitelisted)
+        );
+
+        fs::remove_file(global).unwrap();
+        fs::remove_file(route).unwrap();
+    }
+
+    #[test]
+    fn should_not_whitelist_another_player_with_a_whitelisted_uuid() {
+        // Given
+        let path = write_list(
+            "uuid-whitelist.json",
+            r#"[{"uuid": "069a79f4-44e9-4e26-b06d-5a7e7c3b367f", "name": "Notch"}]"#,
+        );
+        let access = AccessControl::from_files(Some(&path), None);
+
+        // When / Then
+        assert_eq!(
+            access.check("Dinnerbone", Some("069a79f4-44e9-4e26-b06d-5a7e7c3b367f")),
+            Err(AccessDenied::NotWhitelisted)
+        );
+        assert_eq!(
+            access.check("Notch", Some("853c80ef-3c37-49fd-aa49-938b674adae6")),
+            Err(AccessDenied::NotWhitelisted)
+        );
+        assert!(access
+            .check("Notch", Some("069A79F4-44E9-4E26-B06D-5A7E7C3B367F"))
+            .is_ok());
+        assert!(access.check("Notch", None).is_ok());
+
+        fs::remove_file(path).unwrap();
+    }
+}
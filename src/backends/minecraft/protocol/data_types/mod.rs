@@ -1,3 +1,4 @@
 pub(crate) mod string;
 pub(crate) mod unsigned_short;
+pub(crate) mod uuid;
 pub(crate) mod var_int;
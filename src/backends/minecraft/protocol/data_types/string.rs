@@ -1,4 +1,6 @@
-use crate::backends::minecraft::protocol::data_types::var_int::{read_var_int, CONTINUE_BIT};
+use crate::backends::minecraft::protocol::data_types::var_int::{
+    read_var_int, write_var_int, CONTINUE_BIT,
+};
 use std::error::Error;
 use thiserror::Error;
 
@@ -23,3 +25,8 @@ pub(crate) fn read_string(bytes: &[u8], index: &mut usize) -> Result<String, Box
 
     Ok(result.to_string())
 }
+
+pub(crate) fn write_string(buffer: &mut Vec<u8>, value: &str) {
+    write_var_int(buffer, value.len() as i32);
+    buffer.extend_from_slice(value.as_bytes());
+}
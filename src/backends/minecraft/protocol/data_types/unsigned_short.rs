@@ -3,3 +3,7 @@ pub(crate) fn read_unsigned_short(bytes: &[u8], index: &mut usize) -> u16 {
     *index += 2;
     value
 }
+
+pub(crate) fn write_unsigned_short(buffer: &mut Vec<u8>, value: u16) {
+    buffer.extend_from_slice(&value.to_be_bytes());
+}
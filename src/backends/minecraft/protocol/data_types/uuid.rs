@@ -0,0 +1,52 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+#[error("uuid is truncated")]
+pub(crate) struct TruncatedUuidError;
+
+/// Reads a 128 bits UUID and formats it the same way as vanilla's JSON files, lowercase with dashes.
+pub(crate) fn read_uuid(bytes: &[u8], index: &mut usize) -> Result<String, TruncatedUuidError> {
+    let uuid = bytes.get(*index..*index + 16).ok_or(TruncatedUuidError)?;
+    *index += 16;
+
+    let hex = uuid
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+
+    Ok(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_read_uuid() {
+        let bytes = vec![
+            0x06, 0x9a, 0x79, 0xf4, 0x44, 0xe9, 0x4e, 0x26, 0xb0, 0x6d, 0x5a, 0x7e, 0x7c, 0x3b,
+            0x36, 0x7f,
+        ];
+
+        let mut index = 0;
+        let uuid = read_uuid(&bytes, &mut index).unwrap();
+
+        assert_eq!(uuid, "069a79f4-44e9-4e26-b06d-5a7e7c3b367f");
+        assert_eq!(index, 16);
+    }
+
+    #[test]
+    fn should_fail_on_truncated_uuid() {
+        let bytes = vec![0x06, 0x9a, 0x79];
+
+        let mut index = 0;
+        assert!(read_uuid(&bytes, &mut index).is_err());
+    }
+}
@@ -38,6 +38,20 @@ pub(crate) fn read_var_int(bytes: &[u8], index: &mut usize) -> Result<i32, Inval
     Ok(value)
 }
 
+pub(crate) fn write_var_int(buffer: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+
+    loop {
+        if (value & !(SEGMENT_BITS as u32)) == 0 {
+            buffer.push(value as u8);
+            return;
+        }
+
+        buffer.push((value as u8 & SEGMENT_BITS) | CONTINUE_BIT);
+        value >>= 7;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -64,4 +78,23 @@ mod tests {
             assert_eq!(result.unwrap(), expected);
         }
     }
+
+    #[test]
+    fn test_write_var_int() {
+        let test_cases = vec![
+            (0, vec![0x00]),
+            (1, vec![0x01]),
+            (127, vec![0x7f]),
+            (128, vec![0x80, 0x01]),
+            (25565, vec![0xdd, 0xc7, 0x01]),
+            (2147483647, vec![0xff, 0xff, 0xff, 0xff, 0x07]),
+            (-1, vec![0xff, 0xff, 0xff, 0xff, 0x0f]),
+        ];
+
+        for (value, expected) in test_cases {
+            let mut buffer = Vec::new();
+            write_var_int(&mut buffer, value);
+            assert_eq!(buffer, expected);
+        }
+    }
 }
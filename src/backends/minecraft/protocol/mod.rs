@@ -1,4 +1,5 @@
-mod data_types;
+pub(crate) mod data_types;
 pub(crate) mod packets;
 pub(crate) mod parse_packet;
 pub(crate) mod state;
+pub(crate) mod write_packet;
@@ -1,9 +1,12 @@
 use std::error::Error;
 use std::string::String;
 
-use crate::backends::minecraft::protocol::data_types::string::read_string;
-use crate::backends::minecraft::protocol::data_types::unsigned_short::read_unsigned_short;
-use crate::backends::minecraft::protocol::data_types::var_int::read_var_int;
+use crate::backends::minecraft::protocol::data_types::string::{read_string, write_string};
+use crate::backends::minecraft::protocol::data_types::unsigned_short::{
+    read_unsigned_short, write_unsigned_short,
+};
+use crate::backends::minecraft::protocol::data_types::var_int::{read_var_int, write_var_int};
+use crate::backends::minecraft::protocol::write_packet::build_packet;
 
 #[derive(Debug)]
 pub(crate) struct McHandshake {
@@ -30,6 +33,15 @@ pub(crate) fn handle_handshake(
     })
 }
 
+pub(crate) fn build_handshake(handshake: &McHandshake) -> Vec<u8> {
+    let mut body = Vec::new();
+    write_var_int(&mut body, handshake.protocol);
+    write_string(&mut body, &handshake.hostname);
+    write_unsigned_short(&mut body, handshake.port);
+    write_var_int(&mut body, handshake.next_state);
+    build_packet(0x00, &body)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -49,4 +61,24 @@ mod tests {
         assert_eq!(handshake.port, 25565);
         assert_eq!(handshake.next_state, 1);
     }
+
+    #[test]
+    fn should_build_handshake() {
+        let handshake = McHandshake {
+            protocol: 767,
+            hostname: "localhost".to_string(),
+            port: 25565,
+            next_state: 1,
+        };
+
+        let packet = build_handshake(&handshake);
+
+        assert_eq!(
+            packet,
+            vec![
+                0x10, 0x00, 0xff, 0x05, 0x09, 0x6c, 0x6f, 0x63, 0x61, 0x6c, 0x68, 0x6f, 0x73, 0x74,
+                0x63, 0xdd, 0x01,
+            ]
+        );
+    }
 }
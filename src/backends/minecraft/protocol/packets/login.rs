@@ -0,0 +1,86 @@
+use std::error::Error;
+use std::string::String;
+
+use crate::backends::minecraft::protocol::data_types::string::{read_string, write_string};
+use crate::backends::minecraft::protocol::data_types::uuid::read_uuid;
+use crate::backends::minecraft::protocol::write_packet::build_packet;
+
+/// First protocol version (1.19.3) sending an optional UUID in the Login Start packet.
+const OPTIONAL_UUID_PROTOCOL: i32 = 761;
+/// First protocol version (1.20.2) always sending the UUID in the Login Start packet.
+const MANDATORY_UUID_PROTOCOL: i32 = 764;
+
+#[derive(Debug)]
+pub(crate) struct LoginStart {
+    pub(crate) name: String,
+    pub(crate) uuid: Option<String>,
+}
+
+pub(crate) fn handle_login_start(
+    bytes: &[u8],
+    index: &mut usize,
+    protocol: i32,
+) -> Result<LoginStart, Box<dyn Error>> {
+    let name = read_string(bytes, index)?;
+
+    let uuid = if protocol >= MANDATORY_UUID_PROTOCOL {
+        Some(read_uuid(bytes, index)?)
+    } else if protocol >= OPTIONAL_UUID_PROTOCOL && bytes.get(*index) == Some(&0x01) {
+        *index += 1;
+        Some(read_uuid(bytes, index)?)
+    } else {
+        // Older versions either do not send the UUID or send it after the signature data
+        None
+    };
+
+    Ok(LoginStart { name, uuid })
+}
+
+/// Builds a Login Disconnect packet, `reason` must be a JSON text component.
+pub(crate) fn login_disconnect(reason: &str) -> Vec<u8> {
+    let mut body = Vec::new();
+    write_string(&mut body, reason);
+    build_packet(0x00, &body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_login_start_with_uuid() {
+        let login_start_packet = vec![
+            0x06, 0x51, 0x75, 0x6f, 0x7a, 0x75, 0x6c, 0x06, 0x9a, 0x79, 0xf4, 0x44, 0xe9, 0x4e,
+            0x26, 0xb0, 0x6d, 0x5a, 0x7e, 0x7c, 0x3b, 0x36, 0x7f,
+        ];
+
+        let mut index = 0;
+        let login_start = handle_login_start(&login_start_packet, &mut index, 767).unwrap();
+
+        assert_eq!(login_start.name, "Quozul");
+        assert_eq!(
+            login_start.uuid.as_deref(),
+            Some("069a79f4-44e9-4e26-b06d-5a7e7c3b367f")
+        );
+    }
+
+    #[test]
+    fn should_parse_login_start_without_uuid() {
+        let login_start_packet = vec![0x06, 0x51, 0x75, 0x6f, 0x7a, 0x75, 0x6c];
+
+        let mut index = 0;
+        let login_start = handle_login_start(&login_start_packet, &mut index, 47).unwrap();
+
+        assert_eq!(login_start.name, "Quozul");
+        assert_eq!(login_start.uuid, None);
+    }
+
+    #[test]
+    fn should_build_login_disconnect() {
+        let packet = login_disconnect("{\"text\":\"bye\"}");
+
+        assert_eq!(packet[0] as usize, packet.len() - 1);
+        assert_eq!(packet[1], 0x00);
+        assert_eq!(&packet[3..], b"{\"text\":\"bye\"}");
+    }
+}
@@ -1,2 +1,4 @@
 pub(crate) mod get_packet_length;
 pub(crate) mod handshaking;
+pub(crate) mod login;
+pub(crate) mod status;
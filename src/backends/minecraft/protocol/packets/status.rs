@@ -0,0 +1,31 @@
+use crate::backends::minecraft::protocol::data_types::string::write_string;
+use crate::backends::minecraft::protocol::write_packet::build_packet;
+
+/// Builds a Status Response packet, `response` must be the JSON status of the server.
+pub(crate) fn status_response(response: &str) -> Vec<u8> {
+    let mut body = Vec::new();
+    write_string(&mut body, response);
+    build_packet(0x00, &body)
+}
+
+/// Builds a Pong Response packet echoing the payload of the Ping Request.
+pub(crate) fn pong_response(payload: &[u8]) -> Vec<u8> {
+    build_packet(0x01, payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_build_pong_response() {
+        let payload = vec![0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x30, 0x39];
+
+        let packet = pong_response(&payload);
+
+        assert_eq!(
+            packet,
+            vec![0x09, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x30, 0x39]
+        );
+    }
+}
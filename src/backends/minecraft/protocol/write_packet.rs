@@ -0,0 +1,30 @@
+use crate::backends::minecraft::protocol::data_types::var_int::write_var_int;
+
+/// Prefixes the packet id and body with the packet length, ready to be written on the socket.
+pub(crate) fn build_packet(packet_id: i32, body: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(body.len() + 5);
+    write_var_int(&mut data, packet_id);
+    data.extend_from_slice(body);
+
+    let mut packet = Vec::with_capacity(data.len() + 5);
+    write_var_int(&mut packet, data.len() as i32);
+    packet.extend_from_slice(&data);
+    packet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_build_packet() {
+        // Given
+        let body = vec![0x01, 0x02];
+
+        // When
+        let packet = build_packet(0x00, &body);
+
+        // Then
+        assert_eq!(packet, vec![0x03, 0x00, 0x01, 0x02]);
+    }
+}
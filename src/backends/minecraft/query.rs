@@ -0,0 +1,380 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::Value;
+use tokio::net::UdpSocket;
+use tracing::{debug, warn};
+
+use crate::backends::host_table::HostTable;
+use crate::backends::minecraft::route::Route;
+use crate::backends::minecraft::status_cache::{StatusCache, StatusSource};
+use crate::configuration::StatusCacheConfig;
+
+const MAGIC: [u8; 2] = [0xfe, 0xfd];
+const HANDSHAKE: u8 = 0x09;
+const STAT: u8 = 0x00;
+const SESSION_ID_MASK: i32 = 0x0f0f0f0f;
+const CHALLENGE_PERIOD_SECONDS: u64 = 30;
+const QUERY_PROTOCOL: i32 = -1;
+
+#[derive(Debug, PartialEq)]
+enum QueryRequest {
+    Handshake { session_id: i32 },
+    BasicStat { session_id: i32, challenge: i32 },
+    FullStat { session_id: i32, challenge: i32 },
+}
+
+fn parse_request(datagram: &[u8]) -> Option<QueryRequest> {
+    if datagram.get(0..2)? != MAGIC {
+        return None;
+    }
+    let session_id = i32::from_be_bytes(datagram.get(3..7)?.try_into().ok()?) & SESSION_ID_MASK;
+
+    match datagram[2] {
+        HANDSHAKE => Some(QueryRequest::Handshake { session_id }),
+        STAT => {
+            let challenge = i32::from_be_bytes(datagram.get(7..11)?.try_into().ok()?);
+            // Full stat requests are padded with 4 bytes
+            if datagram.len() >= 15 {
+                Some(QueryRequest::FullStat {
+                    session_id,
+                    challenge,
+                })
+            } else {
+                Some(QueryRequest::BasicStat {
+                    session_id,
+                    challenge,
+                })
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Values of the status reported to Query clients.
+#[derive(Debug, PartialEq)]
+struct QueryStatus {
+    motd: String,
+    version: String,
+    online_players: i64,
+    max_players: i64,
+    players: Vec<String>,
+}
+
+impl QueryStatus {
+    fn from_status(status: &Value) -> Self {
+        Self {
+            motd: plain_text(&status["description"]),
+            version: status["version"]["name"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            online_players: status["players"]["online"].as_i64().unwrap_or_default(),
+            max_players: status["players"]["max"].as_i64().unwrap_or_default(),
+            players: status["players"]["sample"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|player| player["name"].as_str())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+}
+
+/// Concatenates the text of a JSON text component.
+fn plain_text(component: &Value) -> String {
+    match component {
+        Value::String(text) => text.clone(),
+        Value::Array(components) => components.iter().map(plain_text).collect(),
+        Value::Object(component) => {
+            let text = component
+                .get("text")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let extra = component.get("extra").map(plain_text).unwrap_or_default();
+            format!("{text}{extra}")
+        }
+        _ => String::new(),
+    }
+}
+
+fn is_datagram_error(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset
+    )
+}
+
+fn push_string(response: &mut Vec<u8>, value: &str) {
+    response.extend_from_slice(value.as_bytes());
+    response.push(0x00);
+}
+
+fn handshake_response(session_id: i32, challenge: i32) -> Vec<u8> {
+    let mut response = vec![HANDSHAKE];
+    response.extend_from_slice(&session_id.to_be_bytes());
+    push_string(&mut response, &challenge.to_string());
+    response
+}
+
+fn basic_stat_response(session_id: i32, status: &QueryStatus, host: SocketAddr) -> Vec<u8> {
+    let mut response = vec![STAT];
+    response.extend_from_slice(&session_id.to_be_bytes());
+    push_string(&mut response, &status.motd);
+    push_string(&mut response, "SMP");
+    push_string(&mut response, "world");
+    push_string(&mut response, &status.online_players.to_string());
+    push_string(&mut response, &status.max_players.to_string());
+    response.extend_from_slice(&host.port().to_le_bytes());
+    push_string(&mut response, &host.ip().to_string());
+    response
+}
+
+fn full_stat_response(session_id: i32, status: &QueryStatus, host: SocketAddr) -> Vec<u8> {
+    let mut response = vec![STAT];
+    response.extend_from_slice(&session_id.to_be_bytes());
+    response.extend_from_slice(b"splitnum\x00\x80\x00");
+
+    let values = [
+        ("hostname", status.motd.clone()),
+        ("gametype", "SMP".to_string()),
+        ("game_id", "MINECRAFT".to_string()),
+        ("version", status.version.clone()),
+        ("plugins", String::new()),
+        ("map", "world".to_string()),
+        ("numplayers", status.online_players.to_string()),
+        ("maxplayers", status.max_players.to_string()),
+        ("hostport", host.port().to_string()),
+        ("hostip", host.ip().to_string()),
+    ];
+    for (key, value) in values {
+        push_string(&mut response, key);
+        push_string(&mut response, &value);
+    }
+    response.push(0x00);
+
+    response.extend_from_slice(b"\x01player_\x00\x00");
+    for player in &status.players {
+        push_string(&mut response, player);
+    }
+    response.push(0x00);
+    response
+}
+
+/// Answers Query requests with the status of a route, so Query works even when the backend has it
+/// disabled.
+pub(crate) struct QueryResponder {
+    routes: Arc<HostTable<Route>>,
+    hostname: String,
+    host: SocketAddr,
+    backend_cache: Option<Arc<StatusCache>>,
+    secret: RandomState,
+}
+
+impl QueryResponder {
+    /// `host` is the address of the Minecraft listener reported to clients.
+    pub(crate) fn new(routes: Arc<HostTable<Route>>, hostname: String, host: SocketAddr) -> Self {
+        // Routes without status cache proxy the status pings, Query needs its own cache
+        let backend_cache = routes
+            .get(&hostname)
+            .filter(|route| route.status_cache.is_none())
+            .map(|route| {
+                Arc::new(StatusCache::new(
+                    StatusSource::Backend(route.target.clone()),
+                    None,
+                    StatusCacheConfig::default(),
+                ))
+            });
+
+        Self {
+            routes,
+            hostname,
+            host,
+            backend_cache,
+            secret: RandomState::new(),
+        }
+    }
+
+    pub(crate) async fn listen(self: Arc<Self>, socket: UdpSocket) -> io::Result<()> {
+        let socket = Arc::new(socket);
+
+        let mut buffer = [0; 1024];
+        loop {
+            let (length, address) = match socket.recv_from(&mut buffer).await {
+                Ok(received) => received,
+                // ICMP errors only concern a single datagram, others go to the supervisor
+                Err(err) if is_datagram_error(&err) => {
+                    debug!("could not receive datagram; error={err}");
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+
+            // Answers may wait for the status of the backend
+            let datagram = buffer[..length].to_vec();
+            let responder = Arc::clone(&self);
+            let socket = Arc::clone(&socket);
+            tokio::spawn(async move {
+                let Some(response) = responder.answer(&datagram, address.ip()).await else {
+                    return;
+                };
+                if let Err(err) = socket.send_to(&response, address).await {
+                    debug!("could not answer query of {address}; error={err}");
+                }
+            });
+        }
+    }
+
+    async fn answer(&self, datagram: &[u8], address: IpAddr) -> Option<Vec<u8>> {
+        match parse_request(datagram)? {
+            QueryRequest::Handshake { session_id } => Some(handshake_response(
+                session_id,
+                self.challenge(address, current_period()),
+            )),
+            QueryRequest::BasicStat {
+                session_id,
+                challenge,
+            } if self.is_valid_challenge(address, challenge) => {
+                let status = self.status().await?;
+                Some(basic_stat_response(session_id, &status, self.host))
+            }
+            QueryRequest::FullStat {
+                session_id,
+                challenge,
+            } if self.is_valid_challenge(address, challenge) => {
+                let status = self.status().await?;
+                Some(full_stat_response(session_id, &status, self.host))
+            }
+            _ => {
+                debug!("query of {address} with invalid challenge");
+                None
+            }
+        }
+    }
+
+    async fn status(&self) -> Option<QueryStatus> {
+        let Some(route) = self.routes.get(&self.hostname) else {
+            warn!("no route for query hostname {}", self.hostname);
+            return None;
+        };
+
+        let response = if route.maintenance.is_enabled() {
+            route.maintenance.status()
+        } else {
+            let cache = route
+                .status_cache
+                .as_ref()
+                .or(self.backend_cache.as_ref())?;
+            cache
+                .get(&self.hostname, self.host.port(), QUERY_PROTOCOL)
+                .await
+        };
+
+        let status = serde_json::from_str::<Value>(&response).ok()?;
+        Some(QueryStatus::from_status(&status))
+    }
+
+    /// Challenges are derived from the client address and change every period, so no state is kept
+    /// between the handshake and the stat request.
+    fn challenge(&self, address: IpAddr, period: u64) -> i32 {
+        (self.secret.hash_one((address, period)) & 0x7fff_ffff) as i32
+    }
+
+    fn is_valid_challenge(&self, address: IpAddr, challenge: i32) -> bool {
+        let period = current_period();
+        challenge == self.challenge(address, period)
+            || challenge == self.challenge(address, period.saturating_sub(1))
+    }
+}
+
+fn current_period() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / CHALLENGE_PERIOD_SECONDS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn status() -> QueryStatus {
+        QueryStatus::from_status(&json!({
+            "version": { "name": "Paper 1.21", "protocol": 767 },
+            "players": { "max": 20, "online": 1, "sample": [
+                { "name": "Notch", "id": "069a79f4-44e9-4e26-b06d-5a7e7c3b367f" },
+            ] },
+            "description": { "text": "A ", "extra": [{ "text": "Server" }] },
+        }))
+    }
+
+    #[test]
+    fn should_parse_requests() {
+        assert_eq!(
+            parse_request(&[0xfe, 0xfd, 0x09, 0x00, 0x00, 0x00, 0x01]),
+            Some(QueryRequest::Handshake { session_id: 1 })
+        );
+        assert_eq!(
+            parse_request(&[0xfe, 0xfd, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02]),
+            Some(QueryRequest::BasicStat {
+                session_id: 1,
+                challenge: 2
+            })
+        );
+        assert_eq!(
+            parse_request(&[
+                0xfe, 0xfd, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00,
+                0x00
+            ]),
+            Some(QueryRequest::FullStat {
+                session_id: 1,
+                challenge: 2
+            })
+        );
+    }
+
+    #[test]
+    fn should_build_handshake_response() {
+        assert_eq!(
+            handshake_response(1, 9513307),
+            b"\x09\x00\x00\x00\x019513307\x00".to_vec()
+        );
+    }
+
+    #[test]
+    fn should_build_basic_stat_response() {
+        // When
+        let response = basic_stat_response(1, &status(), "127.0.0.1:25565".parse().unwrap());
+
+        // Then
+        assert_eq!(
+            response,
+            b"\x00\x00\x00\x00\x01A Server\x00SMP\x00world\x001\x0020\x00\xdd\x63127.0.0.1\x00"
+                .to_vec()
+        );
+    }
+
+    #[test]
+    fn should_build_full_stat_response() {
+        // When
+        let response = full_stat_response(1, &status(), "127.0.0.1:25565".parse().unwrap());
+
+        // Then
+        let expected = [
+            &b"\x00\x00\x00\x00\x01splitnum\x00\x80\x00"[..],
+            b"hostname\x00A Server\x00gametype\x00SMP\x00game_id\x00MINECRAFT\x00",
+            b"version\x00Paper 1.21\x00plugins\x00\x00map\x00world\x00numplayers\x001\x00",
+            b"maxplayers\x0020\x00hostport\x0025565\x00hostip\x00127.0.0.1\x00\x00",
+            b"\x01player_\x00\x00Notch\x00\x00",
+        ]
+        .concat();
+        assert_eq!(response, expected);
+    }
+}
@@ -0,0 +1,143 @@
+use crate::backends::minecraft::aggregate_status::AggregateStatus;
+use crate::backends::minecraft::maintenance::Maintenance;
+use crate::backends::minecraft::player_lists::AccessControl;
+use crate::backends::minecraft::status::fetch_status;
+use crate::backends::minecraft::status_cache::{StatusCache, StatusSource};
+use crate::backends::minecraft::status_override::StatusOverride;
+use crate::configuration::{Host, OutboundConfig, StatusCacheConfig};
+use std::net::IpAddr;
+use std::sync::Arc;
+use tracing::debug;
+
+pub(crate) struct Route {
+    pub(crate) target: String,
+    pub(crate) outbound: OutboundConfig,
+    pub(crate) access: AccessControl,
+    pub(crate) maintenance: Maintenance,
+    pub(crate) status_cache: Option<Arc<StatusCache>>,
+    /// Override of the statuses passing through, for hosts without cache.
+    status_override: Option<StatusOverride>,
+}
+
+impl Route {
+    /// `server_targets` are the targets of every host of the server, used by the status
+    /// aggregation.
+    pub(crate) fn new(
+        host: Host,
+        global_access: &AccessControl,
+        server_targets: &[String],
+    ) -> Self {
+        let access =
+            AccessControl::from_files(host.whitelist.as_ref(), host.banned_players.as_ref());
+
+        // Statuses answered by the proxy go through the cache, aggregated statuses use the
+        // aggregation cache duration. Without a cache, overrides apply to the status of each ping.
+        let cache_config = host.status_cache.or_else(|| {
+            host.aggregate_status
+                .as_ref()
+                .map(|aggregate| StatusCacheConfig {
+                    ttl_seconds: aggregate.cache_seconds,
+                    ..Default::default()
+                })
+        });
+        let status_override = host
+            .status_override
+            .clone()
+            .filter(|_| cache_config.is_none())
+            .map(StatusOverride::new);
+
+        let status_cache = cache_config.map(|config| {
+            let source = match host.aggregate_status {
+                Some(aggregate) => StatusSource::Aggregate(AggregateStatus::new(
+                    &host.target,
+                    aggregate,
+                    server_targets,
+                )),
+                None => StatusSource::Backend(host.target.clone()),
+            };
+            let status_override = host.status_override.map(StatusOverride::new);
+
+            Arc::new(StatusCache::new(source, status_override, config))
+        });
+
+        Self {
+            target: host.target,
+            outbound: host.outbound,
+            access: global_access.merge(&access),
+            maintenance: Maintenance::new(host.maintenance),
+            status_cache,
+            status_override,
+        }
+    }
+
+    /// Status answered by the proxy in place of the backend, if any.
+    pub(crate) async fn proxied_status(
+        &self,
+        address: Option<IpAddr>,
+        hostname: &str,
+        port: u16,
+        protocol: i32,
+    ) -> Option<String> {
+        if self.maintenance.applies_to_address(address) {
+            return Some(self.maintenance.status());
+        }
+
+        if let Some(status_cache) = &self.status_cache {
+            return Some(status_cache.get(hostname, port, protocol).await);
+        }
+
+        // Fetched with the handshake of the client, as the backend would have answered it. A
+        // backend which does not answer gets the connection, as without override.
+        let status_override = self.status_override.as_ref()?;
+        match fetch_status(&self.target, hostname, port, protocol).await {
+            Ok(status) => Some(status_override.apply(status).to_string()),
+            Err(err) => {
+                debug!("Cannot override the status of {hostname}; error={err}");
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::minecraft::protocol::packets::status::status_response;
+    use serde_json::Value;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn should_override_the_status_passing_through_without_cache() {
+        // Given
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 64];
+            let _ = stream.read(&mut request).await;
+            let response = status_response(r#"{"players":{"max":20,"online":1}}"#);
+            stream.write_all(&response).await.unwrap();
+        });
+        let host = toml::from_str::<Host>(&format!(
+            r#"
+            hostname = "localhost"
+            target = "{target}"
+            status_override = {{ motd = "Welcome" }}
+            "#
+        ))
+        .unwrap();
+        let route = Route::new(host, &AccessControl::default(), &[]);
+
+        // When
+        let response = route
+            .proxied_status(Some([127, 0, 0, 1].into()), "localhost", 25565, 767)
+            .await
+            .unwrap();
+
+        // Then
+        assert!(route.status_cache.is_none());
+        let status = serde_json::from_str::<Value>(&response).unwrap();
+        assert_eq!(status["players"]["max"], 20);
+        assert!(status["description"].to_string().contains("Welcome"));
+    }
+}
@@ -0,0 +1,182 @@
+use crate::backends::minecraft::protocol::data_types::var_int::{read_var_int, CONTINUE_BIT};
+use crate::backends::minecraft::protocol::packets::get_packet_length::get_packet_length;
+use crate::backends::minecraft::protocol::packets::handshaking::{build_handshake, McHandshake};
+use crate::backends::minecraft::protocol::write_packet::build_packet;
+use crate::backends::stream::connect;
+use serde_json::{json, Value};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::time::timeout;
+
+const STATUS_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Largest status response: the packet id, then the JSON as a string of up to 32767 UTF-16 code
+/// units, 3 bytes each at most in UTF-8, after its length.
+const MAX_STATUS_LENGTH: usize = 1 + 5 + 32_767 * 3;
+
+/// Status generated by the proxy, the negative protocol marks the version as incompatible so the
+/// client displays the version text.
+pub(crate) fn simple_status(version: &str, motd: &str) -> Value {
+    json!({
+        "version": {
+            "name": version,
+            "protocol": -1,
+        },
+        "players": {
+            "max": 0,
+            "online": 0,
+        },
+        "description": {
+            "text": motd,
+        },
+    })
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum StatusError {
+    #[error("failed to connect to backend; error={0}")]
+    ConnectionFailed(std::io::Error),
+    #[error("failed to exchange status with backend; error={0}")]
+    TransferFailed(std::io::Error),
+    #[error("invalid status response; error={0}")]
+    InvalidResponse(String),
+    #[error("backend did not answer the status request in time")]
+    Timeout,
+    #[error("no backend answered the status request")]
+    NoResponse,
+}
+
+/// Queries the status of a backend the same way a client does from the server list.
+pub(crate) async fn fetch_status(
+    target: &str,
+    hostname: &str,
+    port: u16,
+    protocol: i32,
+) -> Result<Value, StatusError> {
+    let handshake = McHandshake {
+        protocol,
+        hostname: hostname.to_string(),
+        port,
+        next_state: 1,
+    };
+
+    timeout(STATUS_TIMEOUT, exchange_status(target, &handshake))
+        .await
+        .map_err(|_| StatusError::Timeout)?
+}
+
+async fn exchange_status(target: &str, handshake: &McHandshake) -> Result<Value, StatusError> {
+    let mut stream = connect(target)
+        .await
+        .map_err(StatusError::ConnectionFailed)?;
+
+    let request = [build_handshake(handshake), build_packet(0x00, &[])].concat();
+    stream
+        .write_all(&request)
+        .await
+        .map_err(StatusError::TransferFailed)?;
+
+    let mut length_bytes = Vec::with_capacity(5);
+    loop {
+        let byte = stream
+            .read_u8()
+            .await
+            .map_err(StatusError::TransferFailed)?;
+        length_bytes.push(byte);
+        if byte & CONTINUE_BIT == 0 || length_bytes.len() >= 5 {
+            break;
+        }
+    }
+
+    let length = get_packet_length(&length_bytes)
+        .map_err(|err| StatusError::InvalidResponse(err.to_string()))?
+        .packet_length;
+    // The length comes from the backend, checked before allocating
+    if length > MAX_STATUS_LENGTH {
+        return Err(StatusError::InvalidResponse(format!(
+            "response of {length} bytes is too large"
+        )));
+    }
+    let mut packet = vec![0; length];
+    stream
+        .read_exact(&mut packet)
+        .await
+        .map_err(StatusError::TransferFailed)?;
+
+    parse_status_response(&packet)
+}
+
+fn parse_status_response(packet: &[u8]) -> Result<Value, StatusError> {
+    let mut index = 0;
+    let packet_id = read_var_int(packet, &mut index)
+        .map_err(|err| StatusError::InvalidResponse(err.to_string()))?;
+    if packet_id != 0x00 {
+        return Err(StatusError::InvalidResponse(format!(
+            "unexpected packet id {packet_id:#04x}"
+        )));
+    }
+
+    let length = read_var_int(packet, &mut index)
+        .map_err(|err| StatusError::InvalidResponse(err.to_string()))? as usize;
+    let json = index
+        .checked_add(length)
+        .and_then(|end| packet.get(index..end))
+        .ok_or_else(|| StatusError::InvalidResponse("truncated response".to_string()))?;
+
+    serde_json::from_slice(json).map_err(|err| StatusError::InvalidResponse(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::minecraft::protocol::packets::status::status_response;
+
+    #[test]
+    fn should_parse_status_response() {
+        // Given
+        let response = status_response(r#"{"players":{"max":20,"online":1}}"#);
+
+        // When
+        let status = parse_status_response(&response[1..]).unwrap();
+
+        // Then
+        assert_eq!(status["players"]["max"], 20);
+        assert_eq!(status["players"]["online"], 1);
+    }
+
+    #[test]
+    fn should_reject_other_packets() {
+        // Given
+        let packet = vec![0x01, 0x00];
+
+        // When
+        let result = parse_status_response(&packet);
+
+        // Then
+        assert!(matches!(result, Err(StatusError::InvalidResponse(_))));
+    }
+
+    #[tokio::test]
+    async fn should_reject_too_large_responses() {
+        // Given
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            // Length of 1 MiB, without the packet
+            stream.write_all(&[0x80, 0x80, 0x40]).await.unwrap();
+            let mut request = [0; 64];
+            let _ = stream.read(&mut request).await;
+        });
+
+        // When
+        let result = fetch_status(&target, "localhost", 25565, 767).await;
+
+        // Then
+        let Err(StatusError::InvalidResponse(message)) = result else {
+            panic!("expected an invalid response");
+        };
+        assert_eq!(message, "response of 1048576 bytes is too large");
+    }
+}
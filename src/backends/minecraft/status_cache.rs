@@ -0,0 +1,223 @@
+use crate::backends::minecraft::aggregate_status::AggregateStatus;
+use crate::backends::minecraft::status::{fetch_status, simple_status, StatusError};
+use crate::backends::minecraft::status_override::StatusOverride;
+use crate::configuration::StatusCacheConfig;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+const DEFAULT_FALLBACK_MOTD: &str = "Server unavailable";
+const DEFAULT_FALLBACK_VERSION: &str = "Offline";
+
+/// Where the cached status comes from.
+pub(crate) enum StatusSource {
+    Backend(String),
+    Aggregate(AggregateStatus),
+}
+
+impl StatusSource {
+    async fn fetch(&self, hostname: &str, port: u16, protocol: i32) -> Result<Value, StatusError> {
+        match self {
+            StatusSource::Backend(target) => fetch_status(target, hostname, port, protocol).await,
+            StatusSource::Aggregate(aggregate) => aggregate.fetch(hostname, port, protocol).await,
+        }
+    }
+}
+
+enum Freshness {
+    Fresh(String),
+    Stale(String),
+    Missing,
+}
+
+struct CachedStatus {
+    fetched_at: Instant,
+    response: String,
+}
+
+/// Status answered by the proxy, fetched from the source at most once per `ttl`. Once expired, the
+/// previous status is still served for `max_stale` while it is refreshed in the background, and a
+/// fallback status is served when the source cannot be reached.
+pub(crate) struct StatusCache {
+    source: StatusSource,
+    status_override: Option<StatusOverride>,
+    ttl: Duration,
+    max_stale: Duration,
+    fallback: String,
+    cached: Mutex<Option<CachedStatus>>,
+    last_attempt: Mutex<Option<Instant>>,
+    fetching: tokio::sync::Mutex<()>,
+}
+
+impl StatusCache {
+    /// The override only applies to the statuses of the source, not to the fallback status.
+    pub(crate) fn new(
+        source: StatusSource,
+        status_override: Option<StatusOverride>,
+        config: StatusCacheConfig,
+    ) -> Self {
+        let fallback = simple_status(
+            config
+                .fallback_version
+                .as_deref()
+                .unwrap_or(DEFAULT_FALLBACK_VERSION),
+            config
+                .fallback_motd
+                .as_deref()
+                .unwrap_or(DEFAULT_FALLBACK_MOTD),
+        );
+
+        Self {
+            source,
+            status_override,
+            ttl: Duration::from_secs(config.ttl_seconds),
+            max_stale: Duration::from_secs(config.max_stale_seconds),
+            fallback: fallback.to_string(),
+            cached: Mutex::new(None),
+            last_attempt: Mutex::new(None),
+            fetching: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub(crate) async fn get(self: &Arc<Self>, hostname: &str, port: u16, protocol: i32) -> String {
+        match self.cached_response() {
+            Freshness::Fresh(response) => response,
+            Freshness::Stale(response) => {
+                self.revalidate_in_background(hostname, port, protocol);
+                response
+            }
+            Freshness::Missing => self
+                .refresh(hostname, port, protocol)
+                .await
+                .unwrap_or_else(|| self.fallback.clone()),
+        }
+    }
+
+    fn cached_response(&self) -> Freshness {
+        match self.cached.lock().unwrap().as_ref() {
+            Some(cached) if cached.fetched_at.elapsed() < self.ttl => {
+                Freshness::Fresh(cached.response.clone())
+            }
+            Some(cached) if cached.fetched_at.elapsed() < self.ttl + self.max_stale => {
+                Freshness::Stale(cached.response.clone())
+            }
+            _ => Freshness::Missing,
+        }
+    }
+
+    fn attempted_recently(&self) -> bool {
+        self.last_attempt
+            .lock()
+            .unwrap()
+            .is_some_and(|last_attempt| last_attempt.elapsed() < self.ttl)
+    }
+
+    fn revalidate_in_background(self: &Arc<Self>, hostname: &str, port: u16, protocol: i32) {
+        if self.attempted_recently() {
+            return;
+        }
+
+        let cache = Arc::clone(self);
+        let hostname = hostname.to_string();
+        tokio::spawn(async move {
+            cache.refresh(&hostname, port, protocol).await;
+        });
+    }
+
+    /// Fetches the status from the source, unless another ping already did it recently.
+    async fn refresh(&self, hostname: &str, port: u16, protocol: i32) -> Option<String> {
+        let _fetching = self.fetching.lock().await;
+
+        // Another ping may have refreshed the status while waiting for the lock
+        if let Freshness::Fresh(response) = self.cached_response() {
+            return Some(response);
+        }
+        if self.attempted_recently() {
+            return None;
+        }
+
+        *self.last_attempt.lock().unwrap() = Some(Instant::now());
+        match self.source.fetch(hostname, port, protocol).await {
+            Ok(status) => {
+                debug!("Refreshed status of {hostname}");
+                let response = match &self.status_override {
+                    Some(status_override) => status_override.apply(status).to_string(),
+                    None => status.to_string(),
+                };
+                *self.cached.lock().unwrap() = Some(CachedStatus {
+                    fetched_at: Instant::now(),
+                    response: response.clone(),
+                });
+                Some(response)
+            }
+            Err(err) => {
+                warn!("could not refresh status of {hostname}; error={err}");
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache_with(fetched_at: Instant) -> StatusCache {
+        let cache = StatusCache::new(
+            StatusSource::Backend("127.0.0.1:1".to_string()),
+            None,
+            StatusCacheConfig {
+                ttl_seconds: 5,
+                max_stale_seconds: 60,
+                ..Default::default()
+            },
+        );
+        *cache.cached.lock().unwrap() = Some(CachedStatus {
+            fetched_at,
+            response: "{}".to_string(),
+        });
+        cache
+    }
+
+    #[test]
+    fn should_serve_fresh_status() {
+        let cache = cache_with(Instant::now());
+
+        assert!(matches!(cache.cached_response(), Freshness::Fresh(_)));
+    }
+
+    #[test]
+    fn should_serve_stale_status() {
+        let cache = cache_with(Instant::now() - Duration::from_secs(10));
+
+        assert!(matches!(cache.cached_response(), Freshness::Stale(_)));
+    }
+
+    #[test]
+    fn should_discard_expired_status() {
+        let cache = cache_with(Instant::now() - Duration::from_secs(70));
+
+        assert!(matches!(cache.cached_response(), Freshness::Missing));
+    }
+
+    #[tokio::test]
+    async fn should_serve_fallback_when_backend_is_down() {
+        // Given
+        let cache = Arc::new(StatusCache::new(
+            StatusSource::Backend("127.0.0.1:1".to_string()),
+            None,
+            StatusCacheConfig {
+                fallback_motd: Some("Offline".to_string()),
+                ..Default::default()
+            },
+        ));
+
+        // When
+        let response = cache.get("localhost", 25565, 767).await;
+
+        // Then
+        let status = serde_json::from_str::<Value>(&response).unwrap();
+        assert_eq!(status["description"]["text"], "Offline");
+    }
+}
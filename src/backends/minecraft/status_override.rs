@@ -0,0 +1,215 @@
+use crate::configuration::StatusOverrideConfig;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::{json, Value};
+use std::fs;
+use tracing::{error, warn};
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a];
+const FAVICON_SIZE: u32 = 64;
+
+/// Fields of the backend status replaced by the proxy.
+pub(crate) struct StatusOverride {
+    description: Option<Value>,
+    favicon: Option<String>,
+    version_name: Option<String>,
+    version_protocol: Option<i32>,
+    hide_players: bool,
+    hide_sample: bool,
+}
+
+impl StatusOverride {
+    pub(crate) fn new(config: StatusOverrideConfig) -> Self {
+        let favicon = config.favicon.and_then(|path| match load_favicon(&path) {
+            Ok(favicon) => Some(favicon),
+            Err(err) => {
+                error!("cannot load favicon {path}; error={err}");
+                None
+            }
+        });
+
+        Self {
+            description: config.motd.as_deref().map(parse_description),
+            favicon,
+            version_name: config.version_name,
+            version_protocol: config.version_protocol,
+            hide_players: config.hide_players,
+            hide_sample: config.hide_sample,
+        }
+    }
+
+    pub(crate) fn apply(&self, mut status: Value) -> Value {
+        if let Some(description) = &self.description {
+            status["description"] = description.clone();
+        }
+
+        if let Some(favicon) = &self.favicon {
+            status["favicon"] = Value::String(favicon.clone());
+        }
+
+        if let Some(version_name) = &self.version_name {
+            status["version"]["name"] = Value::String(version_name.clone());
+        }
+
+        if let Some(version_protocol) = self.version_protocol {
+            status["version"]["protocol"] = json!(version_protocol);
+        }
+
+        if let Some(status) = status.as_object_mut() {
+            if self.hide_players {
+                // Clients display "???" when the players are missing
+                status.remove("players");
+            } else if self.hide_sample {
+                if let Some(players) = status.get_mut("players").and_then(Value::as_object_mut) {
+                    players.remove("sample");
+                }
+            }
+        }
+
+        status
+    }
+}
+
+/// A MOTD is either a JSON text component or a plain string, which may contain legacy `§` codes.
+fn parse_description(motd: &str) -> Value {
+    match serde_json::from_str::<Value>(motd) {
+        Ok(component) if component.is_object() || component.is_array() => component,
+        _ => json!({ "text": motd }),
+    }
+}
+
+fn load_favicon(path: &str) -> Result<String, String> {
+    let bytes = fs::read(path).map_err(|err| err.to_string())?;
+
+    if !bytes.starts_with(&PNG_SIGNATURE) || bytes.len() < 24 {
+        return Err("the favicon is not a PNG image".to_string());
+    }
+
+    // The width and height are the first fields of the IHDR chunk
+    let width = u32::from_be_bytes([bytes[16], bytes[17], bytes[18], bytes[19]]);
+    let height = u32::from_be_bytes([bytes[20], bytes[21], bytes[22], bytes[23]]);
+    if width != FAVICON_SIZE || height != FAVICON_SIZE {
+        warn!("favicon {path} is {width}x{height}, clients expect {FAVICON_SIZE}x{FAVICON_SIZE}");
+    }
+
+    Ok(format!("data:image/png;base64,{}", STANDARD.encode(bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backend_status() -> Value {
+        json!({
+            "version": { "name": "Paper 1.21", "protocol": 767 },
+            "players": { "max": 20, "online": 1, "sample": [
+                { "name": "Notch", "id": "069a79f4-44e9-4e26-b06d-5a7e7c3b367f" },
+            ] },
+            "description": { "text": "A Minecraft Server" },
+        })
+    }
+
+    #[test]
+    fn should_replace_description_with_legacy_text() {
+        // Given
+        let status_override = StatusOverride::new(StatusOverrideConfig {
+            motd: Some("§aWelcome".to_string()),
+            ..Default::default()
+        });
+
+        // When
+        let status = status_override.apply(backend_status());
+
+        // Then
+        assert_eq!(status["description"], json!({ "text": "§aWelcome" }));
+        assert_eq!(status["players"]["online"], 1);
+    }
+
+    #[test]
+    fn should_replace_description_with_text_component() {
+        // Given
+        let status_override = StatusOverride::new(StatusOverrideConfig {
+            motd: Some(r#"{"text": "Welcome", "color": "green"}"#.to_string()),
+            ..Default::default()
+        });
+
+        // When
+        let status = status_override.apply(backend_status());
+
+        // Then
+        assert_eq!(
+            status["description"],
+            json!({ "text": "Welcome", "color": "green" })
+        );
+    }
+
+    #[test]
+    fn should_replace_version_and_hide_players() {
+        // Given
+        let status_override = StatusOverride::new(StatusOverrideConfig {
+            version_name: Some("Network 1.8-1.21".to_string()),
+            version_protocol: Some(47),
+            hide_players: true,
+            ..Default::default()
+        });
+
+        // When
+        let status = status_override.apply(backend_status());
+
+        // Then
+        assert_eq!(status["version"]["name"], "Network 1.8-1.21");
+        assert_eq!(status["version"]["protocol"], 47);
+        assert!(status.get("players").is_none());
+    }
+
+    #[test]
+    fn should_hide_sample_only() {
+        // Given
+        let status_override = StatusOverride::new(StatusOverrideConfig {
+            hide_sample: true,
+            ..Default::default()
+        });
+
+        // When
+        let status = status_override.apply(backend_status());
+
+        // Then
+        assert_eq!(status["players"]["online"], 1);
+        assert!(status["players"].get("sample").is_none());
+    }
+
+    #[test]
+    fn should_encode_favicon() {
+        // Given
+        let path = std::env::temp_dir().join(format!("proxy-{}-favicon.png", std::process::id()));
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend_from_slice(&[0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52]);
+        png.extend_from_slice(&64u32.to_be_bytes());
+        png.extend_from_slice(&64u32.to_be_bytes());
+        fs::write(&path, &png).unwrap();
+
+        // When
+        let favicon = load_favicon(path.to_str().unwrap()).unwrap();
+
+        // Then
+        assert_eq!(
+            favicon,
+            format!("data:image/png;base64,{}", STANDARD.encode(&png))
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_reject_non_png_favicon() {
+        // Given
+        let path = std::env::temp_dir().join(format!("proxy-{}-favicon.jpg", std::process::id()));
+        fs::write(&path, [0xff, 0xd8, 0xff, 0xe0]).unwrap();
+
+        // When
+        let favicon = load_favicon(path.to_str().unwrap());
+
+        // Then
+        assert!(favicon.is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
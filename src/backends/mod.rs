@@ -1,3 +1,65 @@
+pub(crate) mod bedrock;
+pub(crate) mod host_table;
+pub(crate) mod http;
+pub(crate) mod inherited;
 pub(crate) mod minecraft;
-mod proxy_connection;
+pub(crate) mod proxy_connection;
+pub(crate) mod resolver;
+mod socket_options;
+#[cfg(target_os = "linux")]
+mod splice;
+pub(crate) mod stream;
+pub(crate) mod supervisor;
 pub(crate) mod tcp;
+pub(crate) mod tls;
+pub(crate) mod udp;
+
+use crate::backends::supervisor::{supervise, BindListener, ListenerHealth};
+use crate::configuration::ListenerConfig;
+use std::future::Future;
+use std::io;
+use tokio::task::JoinHandle;
+
+/// Serves every address of a server block with `serve`, once per acceptor, under a supervisor
+/// binding the address again when it fails. The listeners are named after the block, or after
+/// the `kind` of server when it has no name. Several acceptors of an address each bind their own
+/// `SO_REUSEPORT` socket, the kernel spreading the clients among them.
+fn start_listeners<S, F, Fut>(
+    kind: &'static str,
+    config: ListenerConfig,
+    serve: F,
+) -> JoinHandle<()>
+where
+    S: BindListener,
+    F: Fn(S) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = io::Result<()>> + Send + 'static,
+{
+    let mut acceptor_config = config.clone();
+    if config.acceptors > 1 {
+        acceptor_config.socket.reuse_port = true;
+    }
+
+    let name = config.name.as_deref().unwrap_or(kind);
+    let listeners = config
+        .listen
+        .0
+        .iter()
+        .flat_map(|address| {
+            // A Unix socket path can only be bound once
+            let acceptors = if stream::unix_path(address).is_some() {
+                1
+            } else {
+                config.acceptors.max(1)
+            };
+            (0..acceptors).map(|_| address.clone())
+        })
+        .map(|address| {
+            let health = ListenerHealth::register(name, &address);
+            tokio::spawn(supervise(health, acceptor_config.clone(), serve.clone()))
+        })
+        .collect::<Vec<_>>();
+
+    tokio::spawn(async move {
+        futures::future::join_all(listeners).await;
+    })
+}
@@ -1,8 +1,13 @@
-use std::net::SocketAddr;
+#[cfg(target_os = "linux")]
+use crate::backends::splice::splice_bidirectional;
+use crate::backends::stream::{connect_from, PeerAddress, Stream};
+use crate::configuration::OutboundConfig;
+use futures::future::BoxFuture;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
 use thiserror::Error;
 use tokio::io::{copy_bidirectional, AsyncWriteExt};
-use tokio::net::TcpStream;
-use tracing::{error, info};
+use tracing::{debug, info};
 
 #[derive(Error, Debug)]
 pub(crate) enum ProxyConnectionError {
@@ -14,36 +19,249 @@ pub(crate) enum ProxyConnectionError {
     FailedToOpenOutboundConnection(std::io::Error),
 }
 
+static TRAFFIC: TrafficMetrics = TrafficMetrics {
+    connections: AtomicU64::new(0),
+    sent: AtomicU64::new(0),
+    received: AtomicU64::new(0),
+};
+
+/// Counters of the forwarded connections and UDP sessions, exposed through the control socket.
+/// Bytes are sent by the clients to the targets and received from the targets.
+pub(crate) struct TrafficMetrics {
+    connections: AtomicU64,
+    sent: AtomicU64,
+    received: AtomicU64,
+}
+
+impl TrafficMetrics {
+    /// Adds a connection which ended after forwarding these bytes.
+    pub(crate) fn record(&self, sent: u64, received: u64) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+        self.sent.fetch_add(sent, Ordering::Relaxed);
+        self.received.fetch_add(received, Ordering::Relaxed);
+    }
+}
+
+impl Display for TrafficMetrics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "connections={} sent={} received={}",
+            self.connections.load(Ordering::Relaxed),
+            self.sent.load(Ordering::Relaxed),
+            self.received.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// Traffic of every backend.
+pub(crate) fn traffic() -> &'static TrafficMetrics {
+    &TRAFFIC
+}
+
 pub(crate) async fn proxy_connection(
     protocol: &str,
-    inbound: &mut TcpStream,
-    inbound_address: SocketAddr,
+    inbound: &mut Stream,
+    inbound_address: PeerAddress,
     server_addr: &str,
+    outbound: &OutboundConfig,
     initial_bytes: Option<&[u8]>,
 ) -> Result<(), ProxyConnectionError> {
-    info!(
-        "{}:connection from {}:{} forwarded to {}",
+    proxy_connection_with(
         protocol,
-        inbound_address.ip(),
-        inbound_address.port(),
+        inbound,
+        inbound_address,
         server_addr,
+        outbound,
+        initial_bytes,
+        |_, _| Box::pin(async { Ok((0, 0)) }),
+    )
+    .await
+}
+
+/// Proxies the connection as `proxy_connection`, running `exchange` with the client and the
+/// backend once the initial bytes are sent, before forwarding the rest of the bytes. `exchange`
+/// returns the bytes it sent to the backend and received from it.
+pub(crate) async fn proxy_connection_with<F>(
+    protocol: &str,
+    inbound: &mut Stream,
+    inbound_address: PeerAddress,
+    server_addr: &str,
+    outbound: &OutboundConfig,
+    initial_bytes: Option<&[u8]>,
+    exchange: F,
+) -> Result<(), ProxyConnectionError>
+where
+    F: for<'a> FnOnce(&'a mut Stream, &'a mut Stream) -> BoxFuture<'a, std::io::Result<(u64, u64)>>,
+{
+    info!(
+        "{}:connection from {} forwarded to {}",
+        protocol, inbound_address, server_addr,
     );
 
-    match TcpStream::connect(server_addr).await {
-        Ok(mut outbound) => {
-            if let Some(initial_bytes) = initial_bytes {
-                outbound
-                    .write(initial_bytes)
-                    .await
-                    .map_err(ProxyConnectionError::InitialWriteFailed)?;
-            }
-
-            copy_bidirectional(inbound, &mut outbound)
-                .await
-                .map_err(ProxyConnectionError::FailedToTransfer)?;
-
-            Ok(())
-        }
-        Err(err) => Err(ProxyConnectionError::FailedToOpenOutboundConnection(err)),
+    let splice = outbound
+        .socket
+        .as_ref()
+        .is_some_and(|socket| socket.splice == Some(true));
+
+    let mut outbound = connect_from(server_addr, outbound, inbound_address)
+        .await
+        .map_err(ProxyConnectionError::FailedToOpenOutboundConnection)?;
+
+    let mut transferred = (0, 0);
+    let result = transfer(
+        inbound,
+        &mut outbound,
+        initial_bytes,
+        exchange,
+        splice,
+        &mut transferred,
+    )
+    .await;
+    let (sent, received) = transferred;
+    traffic().record(sent, received);
+    debug!(
+        "{}:connection from {} closed; sent={} received={}",
+        protocol, inbound_address, sent, received,
+    );
+
+    result
+}
+
+/// Sends the initial bytes, runs `exchange` and forwards the rest of the bytes, adding the bytes
+/// sent and received to `transferred` until one of them fails.
+async fn transfer<F>(
+    inbound: &mut Stream,
+    outbound: &mut Stream,
+    initial_bytes: Option<&[u8]>,
+    exchange: F,
+    splice: bool,
+    transferred: &mut (u64, u64),
+) -> Result<(), ProxyConnectionError>
+where
+    F: for<'a> FnOnce(&'a mut Stream, &'a mut Stream) -> BoxFuture<'a, std::io::Result<(u64, u64)>>,
+{
+    if let Some(initial_bytes) = initial_bytes {
+        outbound
+            .write_all(initial_bytes)
+            .await
+            .map_err(ProxyConnectionError::InitialWriteFailed)?;
+        transferred.0 += initial_bytes.len() as u64;
+    }
+
+    let (sent, received) = exchange(inbound, outbound)
+        .await
+        .map_err(ProxyConnectionError::FailedToTransfer)?;
+    transferred.0 += sent;
+    transferred.1 += received;
+
+    let (sent, received) = forward(inbound, outbound, splice)
+        .await
+        .map_err(ProxyConnectionError::FailedToTransfer)?;
+    transferred.0 += sent;
+    transferred.1 += received;
+
+    Ok(())
+}
+
+/// Forwards the bytes between the client and the backend, with splice(2) on Linux if enabled.
+async fn forward(
+    inbound: &mut Stream,
+    outbound: &mut Stream,
+    splice: bool,
+) -> std::io::Result<(u64, u64)> {
+    #[cfg(target_os = "linux")]
+    if splice {
+        return splice_bidirectional(inbound, outbound).await;
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = splice;
+
+    copy_bidirectional(inbound, outbound).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::UnixStream;
+
+    fn stream_pair() -> (Stream, UnixStream) {
+        let (stream, peer) = UnixStream::pair().unwrap();
+        (Stream::Unix(stream), peer)
+    }
+
+    #[tokio::test]
+    async fn should_count_the_initial_exchanged_and_forwarded_bytes() {
+        // Given
+        let (mut inbound, mut client) = stream_pair();
+        let (mut outbound, mut backend) = stream_pair();
+        client.write_all(b"request").await.unwrap();
+        client.shutdown().await.unwrap();
+        backend.write_all(b"response").await.unwrap();
+        backend.shutdown().await.unwrap();
+        let mut transferred = (0, 0);
+
+        // When
+        let result = transfer(
+            &mut inbound,
+            &mut outbound,
+            Some(b"head"),
+            |_, _| Box::pin(async { Ok((3, 5)) }),
+            false,
+            &mut transferred,
+        )
+        .await;
+
+        // Then
+        assert!(result.is_ok());
+        assert_eq!(transferred, (4 + 3 + 7, 5 + 8));
+        let mut received = Vec::new();
+        backend.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"headrequest");
+    }
+
+    #[tokio::test]
+    async fn should_keep_the_bytes_sent_before_a_failure() {
+        // Given
+        let (mut inbound, _client) = stream_pair();
+        let (mut outbound, _backend) = stream_pair();
+        let mut transferred = (0, 0);
+
+        // When
+        let result = transfer(
+            &mut inbound,
+            &mut outbound,
+            Some(b"head"),
+            |_, _| Box::pin(async { Err(io::ErrorKind::ConnectionReset.into()) }),
+            false,
+            &mut transferred,
+        )
+        .await;
+
+        // Then
+        assert!(matches!(
+            result,
+            Err(ProxyConnectionError::FailedToTransfer(_))
+        ));
+        assert_eq!(transferred, (4, 0));
+    }
+
+    #[test]
+    fn should_count_connections_and_bytes() {
+        // Given
+        let metrics = TrafficMetrics {
+            connections: AtomicU64::new(0),
+            sent: AtomicU64::new(0),
+            received: AtomicU64::new(0),
+        };
+
+        // When
+        metrics.record(10, 200);
+        metrics.record(5, 0);
+
+        // Then
+        assert_eq!(metrics.to_string(), "connections=2 sent=15 received=200");
     }
 }
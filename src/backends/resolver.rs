@@ -0,0 +1,564 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::hash::BuildHasher;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use hickory_resolver::config::{LookupIpStrategy, ResolverConfig, ResolverOpts};
+use hickory_resolver::error::ResolveErrorKind;
+use hickory_resolver::{system_conf, TokioAsyncResolver};
+use tracing::{debug, warn};
+
+use crate::configuration::DnsConfig;
+
+const SRV_PREFIX: &str = "srv:";
+const MINECRAFT_SERVICE: &str = "_minecraft._tcp.";
+const DEFAULT_PORT: u16 = 25565;
+
+static RESOLVER: OnceLock<Resolver> = OnceLock::new();
+
+/// Name of a `srv:play.example.com` address.
+pub(crate) fn srv_name(address: &str) -> Option<&str> {
+    address.strip_prefix(SRV_PREFIX)
+}
+
+/// Addresses of one SRV record, or of the A/AAAA records of a host.
+#[derive(Debug, Clone)]
+struct Endpoint {
+    priority: u16,
+    weight: u16,
+    addresses: Vec<SocketAddr>,
+}
+
+struct CachedEndpoints {
+    valid_until: Instant,
+    stale_until: Instant,
+    endpoints: Vec<Endpoint>,
+}
+
+/// Counters of the resolver, exposed through the control socket.
+#[derive(Default)]
+pub(crate) struct ResolverMetrics {
+    lookups: AtomicU64,
+    failures: AtomicU64,
+    stale_answers: AtomicU64,
+}
+
+impl Display for ResolverMetrics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "lookups={} failures={} stale_answers={}",
+            self.lookups.load(Ordering::Relaxed),
+            self.failures.load(Ordering::Relaxed),
+            self.stale_answers.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// Resolves the targets of every backend, caching the records for their TTL and serving stale
+/// records when the DNS servers cannot be reached.
+pub(crate) struct Resolver {
+    resolver: TokioAsyncResolver,
+    config: DnsConfig,
+    cache: Mutex<HashMap<String, CachedEndpoints>>,
+    metrics: ResolverMetrics,
+}
+
+/// Sets the configuration of the shared resolver, before any target is resolved.
+pub(crate) fn configure_resolver(config: DnsConfig) {
+    if RESOLVER.set(Resolver::from_system_conf(config)).is_err() {
+        warn!("The resolver is already configured");
+    }
+}
+
+/// Resolver shared by every backend.
+pub(crate) fn resolver() -> &'static Resolver {
+    RESOLVER.get_or_init(|| Resolver::from_system_conf(DnsConfig::default()))
+}
+
+impl Resolver {
+    fn from_system_conf(config: DnsConfig) -> Self {
+        let (servers, options) = system_conf::read_system_conf().unwrap_or_else(|err| {
+            warn!("Cannot read the system DNS configuration, using defaults; error={err}");
+            (ResolverConfig::default(), ResolverOpts::default())
+        });
+        Self::new(servers, options, config)
+    }
+
+    fn new(servers: ResolverConfig, mut options: ResolverOpts, config: DnsConfig) -> Self {
+        // Records are cached here, with the TTL bounds and stale answers of the configuration
+        options.cache_size = 0;
+        options.ip_strategy = LookupIpStrategy::Ipv4AndIpv6;
+        Self {
+            resolver: TokioAsyncResolver::tokio(servers, options),
+            config,
+            cache: Mutex::new(HashMap::new()),
+            metrics: ResolverMetrics::default(),
+        }
+    }
+
+    pub(crate) fn metrics(&self) -> &ResolverMetrics {
+        &self.metrics
+    }
+
+    /// Delay before trying the next address while a connection attempt is pending.
+    pub(crate) fn happy_eyeballs_delay(&self) -> Duration {
+        Duration::from_millis(self.config.happy_eyeballs_delay_ms)
+    }
+
+    /// Addresses of a `host:port` or `srv:` target, in the order they should be tried.
+    pub(crate) async fn resolve(&self, target: &str) -> io::Result<Vec<SocketAddr>> {
+        if let Ok(address) = target.parse::<SocketAddr>() {
+            return Ok(vec![address]);
+        }
+
+        let now = Instant::now();
+        let cached = self.cache.lock().unwrap().get(target).map(|cached| {
+            (
+                cached.valid_until,
+                cached.stale_until,
+                cached.endpoints.clone(),
+            )
+        });
+        if let Some((valid_until, _, endpoints)) = &cached {
+            if *valid_until > now {
+                return Ok(order_endpoints(endpoints.clone()));
+            }
+        }
+
+        self.metrics.lookups.fetch_add(1, Ordering::Relaxed);
+        let lookup = match srv_name(target) {
+            Some(name) => self.lookup_srv(name).await,
+            None => self.lookup_host(target).await,
+        };
+
+        match lookup {
+            Ok((endpoints, valid_until)) => {
+                // Not `clamp`, which panics when the bounds are reversed
+                let valid_until = valid_until
+                    .max(now + Duration::from_secs(self.config.min_ttl_seconds))
+                    .min(now + Duration::from_secs(self.config.max_ttl_seconds));
+                self.cache.lock().unwrap().insert(
+                    target.to_string(),
+                    CachedEndpoints {
+                        valid_until,
+                        stale_until: valid_until
+                            + Duration::from_secs(self.config.max_stale_seconds),
+                        endpoints: endpoints.clone(),
+                    },
+                );
+                Ok(order_endpoints(endpoints))
+            }
+            Err(err) => {
+                self.metrics.failures.fetch_add(1, Ordering::Relaxed);
+                match cached {
+                    Some((_, stale_until, endpoints)) if stale_until > now => {
+                        warn!("Cannot resolve {target}, using stale records; error={err}");
+                        self.metrics.stale_answers.fetch_add(1, Ordering::Relaxed);
+                        Ok(order_endpoints(endpoints))
+                    }
+                    _ => {
+                        warn!("Cannot resolve {target}; error={err}");
+                        Err(err)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Looks up the A/AAAA records of a `host:port` target.
+    async fn lookup_host(&self, target: &str) -> io::Result<(Vec<Endpoint>, Instant)> {
+        let (host, port) = target
+            .rsplit_once(':')
+            .and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?)))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid address {target}"),
+                )
+            })?;
+        self.lookup_ip(host.trim_start_matches('[').trim_end_matches(']'), port)
+            .await
+    }
+
+    async fn lookup_ip(&self, host: &str, port: u16) -> io::Result<(Vec<Endpoint>, Instant)> {
+        let ips = self
+            .resolver
+            .lookup_ip(host)
+            .await
+            .map_err(io::Error::other)?;
+        let endpoint = Endpoint {
+            priority: 0,
+            weight: 0,
+            addresses: interleave_families(ips.iter().map(|ip| (ip, port).into()).collect()),
+        };
+        Ok((vec![endpoint], ips.valid_until()))
+    }
+
+    /// Looks up the `_minecraft._tcp` records of a name, or its A/AAAA records when it has none.
+    /// Names starting with `_` are queried as is and have no fallback.
+    async fn lookup_srv(&self, name: &str) -> io::Result<(Vec<Endpoint>, Instant)> {
+        let explicit_service = name.starts_with('_');
+        let service = if explicit_service {
+            name.to_string()
+        } else {
+            format!("{MINECRAFT_SERVICE}{name}")
+        };
+
+        let records = match self.resolver.srv_lookup(service.as_str()).await {
+            Ok(records) => records,
+            Err(err)
+                if !explicit_service
+                    && matches!(err.kind(), ResolveErrorKind::NoRecordsFound { .. }) =>
+            {
+                debug!("No SRV record for {service}, resolving {name}");
+                return self.lookup_ip(name, DEFAULT_PORT).await;
+            }
+            Err(err) => return Err(io::Error::other(err)),
+        };
+
+        let mut valid_until = records.as_lookup().valid_until();
+        let mut endpoints = Vec::new();
+        let mut failure = None;
+        for record in records.iter() {
+            // A target of "." means the service is not available at this name
+            if record.target().is_root() {
+                continue;
+            }
+            match self.resolver.lookup_ip(record.target().clone()).await {
+                Ok(ips) => {
+                    valid_until = valid_until.min(ips.valid_until());
+                    let addresses = ips.iter().map(|ip| (ip, record.port()).into()).collect();
+                    endpoints.push(Endpoint {
+                        priority: record.priority(),
+                        weight: record.weight(),
+                        addresses: interleave_families(addresses),
+                    });
+                }
+                Err(err) => {
+                    warn!("Cannot resolve {}; error={err}", record.target());
+                    failure = Some(err);
+                }
+            }
+        }
+
+        // Failing to resolve every target is not cached, for a previous answer to be served
+        match failure {
+            Some(err) if endpoints.is_empty() => Err(io::Error::other(err)),
+            _ => Ok((endpoints, valid_until)),
+        }
+    }
+}
+
+/// Alternates IPv6 and IPv4 addresses, starting with IPv6, as in RFC 8305.
+fn interleave_families(addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let (ipv6, ipv4): (Vec<_>, Vec<_>) = addresses.into_iter().partition(SocketAddr::is_ipv6);
+    let mut ipv6 = ipv6.into_iter();
+    let mut ipv4 = ipv4.into_iter();
+
+    let mut interleaved = Vec::new();
+    loop {
+        match (ipv6.next(), ipv4.next()) {
+            (None, None) => return interleaved,
+            (first, second) => interleaved.extend(first.into_iter().chain(second)),
+        }
+    }
+}
+
+/// Orders endpoints by priority, then randomly by weight within a priority as in RFC 2782.
+fn order_endpoints(mut endpoints: Vec<Endpoint>) -> Vec<SocketAddr> {
+    endpoints.sort_by_key(|endpoint| endpoint.priority);
+
+    let mut addresses = Vec::new();
+    for priority in endpoints.chunk_by(|a, b| a.priority == b.priority) {
+        let mut remaining = priority.to_vec();
+        while !remaining.is_empty() {
+            let total: u32 = remaining
+                .iter()
+                .map(|endpoint| endpoint.weight as u32)
+                .sum();
+            let index = if total == 0 {
+                random(remaining.len() as u32) as usize
+            } else {
+                let mut pick = random(total);
+                remaining
+                    .iter()
+                    .position(|endpoint| {
+                        let weight = endpoint.weight as u32;
+                        if pick < weight {
+                            true
+                        } else {
+                            pick -= weight;
+                            false
+                        }
+                    })
+                    .unwrap_or(0)
+            };
+            addresses.extend(remaining.remove(index).addresses);
+        }
+    }
+
+    addresses
+}
+
+/// Random number below `bound`, `RandomState` is seeded differently on every call.
+fn random(bound: u32) -> u32 {
+    (RandomState::new().hash_one(Instant::now()) % bound as u64) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::atomic::{AtomicBool, AtomicUsize};
+    use std::sync::Arc;
+
+    use hickory_resolver::config::NameServerConfigGroup;
+    use hickory_resolver::proto::op::{Message, MessageType, ResponseCode};
+    use hickory_resolver::proto::rr::rdata::{A, SRV};
+    use hickory_resolver::proto::rr::{Name, RData, Record, RecordType};
+    use tokio::net::UdpSocket;
+
+    use super::*;
+
+    /// Stub DNS server answering from fixed records, or failing every query while `failing` is set
+    /// and the address queries while `failing_addresses` is set.
+    struct DnsServer {
+        address: SocketAddr,
+        queries: Arc<AtomicUsize>,
+        failing: Arc<AtomicBool>,
+        failing_addresses: Arc<AtomicBool>,
+    }
+
+    async fn start_dns_server(records: Vec<Record>) -> DnsServer {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server = DnsServer {
+            address: socket.local_addr().unwrap(),
+            queries: Arc::new(AtomicUsize::new(0)),
+            failing: Arc::new(AtomicBool::new(false)),
+            failing_addresses: Arc::new(AtomicBool::new(false)),
+        };
+        let queries = server.queries.clone();
+        let failing = server.failing.clone();
+        let failing_addresses = server.failing_addresses.clone();
+
+        tokio::spawn(async move {
+            let mut buf = [0; 512];
+            while let Ok((length, client)) = socket.recv_from(&mut buf).await {
+                queries.fetch_add(1, Ordering::SeqCst);
+                let request = Message::from_vec(&buf[..length]).unwrap();
+                let query = request.queries()[0].clone();
+
+                let mut response = Message::new();
+                response
+                    .set_id(request.id())
+                    .set_message_type(MessageType::Response)
+                    .set_recursion_available(true)
+                    .add_query(query.clone());
+                let is_address = query.query_type() != RecordType::SRV;
+                if failing.load(Ordering::SeqCst)
+                    || (is_address && failing_addresses.load(Ordering::SeqCst))
+                {
+                    response.set_response_code(ResponseCode::ServFail);
+                } else if !records.iter().any(|record| record.name() == query.name()) {
+                    response.set_response_code(ResponseCode::NXDomain);
+                } else {
+                    response.add_answers(
+                        records
+                            .iter()
+                            .filter(|record| {
+                                record.name() == query.name()
+                                    && record.record_type() == query.query_type()
+                            })
+                            .cloned(),
+                    );
+                }
+                socket
+                    .send_to(&response.to_vec().unwrap(), client)
+                    .await
+                    .unwrap();
+            }
+        });
+
+        server
+    }
+
+    fn resolver_for(server: &DnsServer, config: DnsConfig) -> Resolver {
+        let address = server.address;
+        let servers = NameServerConfigGroup::from_ips_clear(&[address.ip()], address.port(), true);
+        Resolver::new(
+            ResolverConfig::from_parts(None, vec![], servers),
+            ResolverOpts::default(),
+            config,
+        )
+    }
+
+    fn srv(name: &str, priority: u16, port: u16, target: &str) -> Record {
+        let name = Name::from_ascii(name).unwrap();
+        let target = Name::from_ascii(target).unwrap();
+        Record::from_rdata(name, 60, RData::SRV(SRV::new(priority, 10, port, target)))
+    }
+
+    fn a(name: &str, ip: [u8; 4]) -> Record {
+        let name = Name::from_ascii(name).unwrap();
+        Record::from_rdata(name, 60, RData::A(A::from(Ipv4Addr::from(ip))))
+    }
+
+    #[tokio::test]
+    async fn should_order_srv_records_by_priority() {
+        // Given
+        let server = start_dns_server(vec![
+            srv(
+                "_minecraft._tcp.play.internal.",
+                20,
+                25570,
+                "backup.internal.",
+            ),
+            srv(
+                "_minecraft._tcp.play.internal.",
+                10,
+                25566,
+                "main.internal.",
+            ),
+            a("main.internal.", [10, 0, 0, 1]),
+            a("backup.internal.", [10, 0, 0, 2]),
+        ])
+        .await;
+        let resolver = resolver_for(&server, DnsConfig::default());
+
+        // When
+        let addresses = resolver.resolve("srv:play.internal.").await.unwrap();
+
+        // Then
+        assert_eq!(
+            addresses,
+            vec![
+                SocketAddr::new(IpAddr::from([10, 0, 0, 1]), 25566),
+                SocketAddr::new(IpAddr::from([10, 0, 0, 2]), 25570),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn should_fall_back_to_address_records() {
+        // Given
+        let server = start_dns_server(vec![a("play.internal.", [10, 0, 0, 3])]).await;
+        let resolver = resolver_for(&server, DnsConfig::default());
+
+        // When
+        let addresses = resolver.resolve("srv:play.internal.").await.unwrap();
+
+        // Then
+        assert_eq!(
+            addresses,
+            vec![SocketAddr::new(IpAddr::from([10, 0, 0, 3]), DEFAULT_PORT)]
+        );
+    }
+
+    #[tokio::test]
+    async fn should_cache_records_until_their_ttl() {
+        // Given
+        let server = start_dns_server(vec![a("play.internal.", [10, 0, 0, 1])]).await;
+        let resolver = resolver_for(&server, DnsConfig::default());
+        resolver.resolve("play.internal.:25566").await.unwrap();
+        let first_queries = server.queries.load(Ordering::SeqCst);
+
+        // When
+        let addresses = resolver.resolve("play.internal.:25566").await.unwrap();
+
+        // Then
+        assert_eq!(
+            addresses,
+            vec![SocketAddr::new(IpAddr::from([10, 0, 0, 1]), 25566)]
+        );
+        assert_eq!(server.queries.load(Ordering::SeqCst), first_queries);
+    }
+
+    #[tokio::test]
+    async fn should_serve_stale_records_when_resolution_fails() {
+        // Given
+        let server = start_dns_server(vec![a("play.internal.", [10, 0, 0, 1])]).await;
+        let config = DnsConfig {
+            max_ttl_seconds: 0,
+            ..DnsConfig::default()
+        };
+        let resolver = resolver_for(&server, config);
+        resolver.resolve("play.internal.:25566").await.unwrap();
+        server.failing.store(true, Ordering::SeqCst);
+
+        // When
+        let addresses = resolver.resolve("play.internal.:25566").await.unwrap();
+
+        // Then
+        assert_eq!(
+            addresses,
+            vec![SocketAddr::new(IpAddr::from([10, 0, 0, 1]), 25566)]
+        );
+        assert_eq!(
+            resolver.metrics().to_string(),
+            "lookups=2 failures=1 stale_answers=1"
+        );
+    }
+
+    #[tokio::test]
+    async fn should_serve_stale_records_when_no_srv_target_resolves() {
+        // Given
+        let server = start_dns_server(vec![
+            srv(
+                "_minecraft._tcp.play.internal.",
+                10,
+                25566,
+                "main.internal.",
+            ),
+            a("main.internal.", [10, 0, 0, 1]),
+        ])
+        .await;
+        let config = DnsConfig {
+            max_ttl_seconds: 0,
+            ..DnsConfig::default()
+        };
+        let resolver = resolver_for(&server, config);
+        resolver.resolve("srv:play.internal.").await.unwrap();
+        server.failing_addresses.store(true, Ordering::SeqCst);
+
+        // When
+        let addresses = resolver.resolve("srv:play.internal.").await.unwrap();
+
+        // Then
+        assert_eq!(
+            addresses,
+            vec![SocketAddr::new(IpAddr::from([10, 0, 0, 1]), 25566)]
+        );
+        assert_eq!(
+            resolver.metrics().to_string(),
+            "lookups=2 failures=1 stale_answers=1"
+        );
+    }
+
+    #[test]
+    fn should_alternate_address_families() {
+        // Given
+        let addresses = vec![
+            SocketAddr::from(([10, 0, 0, 1], 1)),
+            SocketAddr::from(([10, 0, 0, 2], 1)),
+            "[::1]:1".parse().unwrap(),
+        ];
+
+        // When
+        let addresses = interleave_families(addresses);
+
+        // Then
+        assert_eq!(
+            addresses,
+            vec![
+                "[::1]:1".parse().unwrap(),
+                SocketAddr::from(([10, 0, 0, 1], 1)),
+                SocketAddr::from(([10, 0, 0, 2], 1)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,128 @@
+use std::io;
+use std::mem::size_of;
+use std::os::fd::AsRawFd;
+use std::time::Duration;
+
+use socket2::{Socket, TcpKeepalive};
+#[cfg(not(target_os = "linux"))]
+use tracing::debug;
+
+use crate::configuration::SocketConfig;
+
+const LISTEN_BACKLOG: i32 = 1024;
+
+/// Number of pending TCP Fast Open connections of a listener.
+const FASTOPEN_QUEUE_LENGTH: libc::c_int = 256;
+
+/// Applies the options of a server block to a listening TCP socket. Accepted connections inherit
+/// the options of the listener.
+pub(crate) fn configure_listener(socket: &Socket, config: &SocketConfig) -> io::Result<()> {
+    configure_stream(socket, config)?;
+    if config.reuse_port {
+        socket.set_reuse_port(true)?;
+    }
+    if config.fastopen == Some(true) {
+        set_tcp_option(socket, libc::TCP_FASTOPEN, FASTOPEN_QUEUE_LENGTH)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn listen_backlog(config: &SocketConfig) -> i32 {
+    config.backlog.unwrap_or(LISTEN_BACKLOG)
+}
+
+/// Applies the options of a route to a socket connecting to its target.
+pub(crate) fn configure_outbound(socket: &Socket, config: &SocketConfig) -> io::Result<()> {
+    configure_stream(socket, config)?;
+    if config.fastopen == Some(true) {
+        set_fastopen_connect(socket)?;
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_fastopen_connect(socket: &Socket) -> io::Result<()> {
+    set_tcp_option(socket, libc::TCP_FASTOPEN_CONNECT, 1)
+}
+
+/// Other systems only send data with the SYN through their own connect calls.
+#[cfg(not(target_os = "linux"))]
+fn set_fastopen_connect(_: &Socket) -> io::Result<()> {
+    debug!("TCP Fast Open of the connections to targets is only supported on Linux");
+    Ok(())
+}
+
+/// Applies the options of a server block meaningful to a UDP socket.
+pub(crate) fn configure_datagram(socket: &Socket, config: &SocketConfig) -> io::Result<()> {
+    configure_buffers(socket, config)?;
+    if config.reuse_port {
+        socket.set_reuse_port(true)?;
+    }
+    Ok(())
+}
+
+fn configure_stream(socket: &Socket, config: &SocketConfig) -> io::Result<()> {
+    if let Some(nodelay) = config.nodelay {
+        socket.set_nodelay(nodelay)?;
+    }
+    if let Some(seconds) = config.keepalive_seconds {
+        let keepalive = TcpKeepalive::new().with_time(Duration::from_secs(seconds));
+        socket.set_tcp_keepalive(&keepalive)?;
+    }
+    configure_buffers(socket, config)
+}
+
+fn configure_buffers(socket: &Socket, config: &SocketConfig) -> io::Result<()> {
+    if let Some(size) = config.send_buffer_size {
+        socket.set_send_buffer_size(size)?;
+    }
+    if let Some(size) = config.recv_buffer_size {
+        socket.set_recv_buffer_size(size)?;
+    }
+    Ok(())
+}
+
+/// Sets the TCP Fast Open options, which socket2 has no setter for.
+fn set_tcp_option(socket: &Socket, option: libc::c_int, value: libc::c_int) -> io::Result<()> {
+    // SAFETY: the value points to a c_int living for the duration of the call
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            option,
+            &value as *const libc::c_int as *const libc::c_void,
+            size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use socket2::{Domain, Type};
+
+    #[test]
+    fn should_apply_socket_options() {
+        // Given
+        let socket = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
+        let config = SocketConfig {
+            nodelay: Some(true),
+            keepalive_seconds: Some(30),
+            reuse_port: true,
+            fastopen: Some(true),
+            ..Default::default()
+        };
+
+        // When
+        configure_listener(&socket, &config).unwrap();
+
+        // Then
+        assert!(socket.nodelay().unwrap());
+        assert_eq!(socket.keepalive_time().unwrap(), Duration::from_secs(30));
+        assert!(socket.reuse_port().unwrap());
+    }
+}
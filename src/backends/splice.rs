@@ -0,0 +1,209 @@
+use std::io;
+use std::net::Shutdown;
+use std::os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+use socket2::SockRef;
+use tokio::io::Interest;
+
+use crate::backends::stream::Stream;
+
+/// Maximum number of bytes moved by a single splice, the default capacity of a pipe.
+const SPLICE_LENGTH: usize = 65536;
+
+/// Forwards the bytes between two streams through pipes with splice(2), so they are never copied
+/// to userspace. Returns the number of bytes sent from `a` to `b` and from `b` to `a`.
+pub(crate) async fn splice_bidirectional(a: &Stream, b: &Stream) -> io::Result<(u64, u64)> {
+    let a_to_b = Pipe::new()?;
+    let b_to_a = Pipe::new()?;
+
+    tokio::try_join!(splice_one_way(a, b, &a_to_b), splice_one_way(b, a, &b_to_a))
+}
+
+/// Moves bytes from `from` to `to` until the end of `from`, then shuts down the writes of `to`.
+async fn splice_one_way(from: &Stream, to: &Stream, pipe: &Pipe) -> io::Result<u64> {
+    let mut transferred = 0;
+
+    loop {
+        // The pipe is drained after each read, so only the sockets can make a splice block
+        let read = from
+            .async_io(Interest::READABLE, || {
+                splice(
+                    from.as_fd().as_raw_fd(),
+                    pipe.write.as_raw_fd(),
+                    SPLICE_LENGTH,
+                )
+            })
+            .await?;
+        if read == 0 {
+            SockRef::from(to).shutdown(Shutdown::Write)?;
+            return Ok(transferred);
+        }
+
+        let mut pending = read;
+        while pending > 0 {
+            pending -= to
+                .async_io(Interest::WRITABLE, || {
+                    splice(pipe.read.as_raw_fd(), to.as_fd().as_raw_fd(), pending)
+                })
+                .await?;
+        }
+        transferred += read as u64;
+    }
+}
+
+struct Pipe {
+    read: OwnedFd,
+    write: OwnedFd,
+}
+
+impl Pipe {
+    fn new() -> io::Result<Self> {
+        let mut fds = [0; 2];
+        // SAFETY: pipe2 writes two file descriptors to the array, owned by the pipe from now on
+        unsafe {
+            if libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self {
+                read: OwnedFd::from_raw_fd(fds[0]),
+                write: OwnedFd::from_raw_fd(fds[1]),
+            })
+        }
+    }
+}
+
+fn splice(from: RawFd, to: RawFd, length: usize) -> io::Result<usize> {
+    // SAFETY: both file descriptors are open for the duration of the call and no offset is used
+    let result = unsafe {
+        libc::splice(
+            from,
+            std::ptr::null_mut(),
+            to,
+            std::ptr::null_mut(),
+            length,
+            libc::SPLICE_F_MOVE | libc::SPLICE_F_NONBLOCK,
+        )
+    };
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(result as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use tokio::io::{copy_bidirectional, AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::*;
+
+    /// Client and backend connected through the two streams of a proxy.
+    async fn proxied_connection() -> (TcpStream, Stream, Stream, TcpStream) {
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+        let client = TcpStream::connect(proxy.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (inbound, _) = proxy.accept().await.unwrap();
+        let outbound = TcpStream::connect(backend.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, _) = backend.accept().await.unwrap();
+
+        (client, Stream::Tcp(inbound), Stream::Tcp(outbound), server)
+    }
+
+    #[tokio::test]
+    async fn should_forward_both_ways_and_count_bytes() {
+        // Given
+        let (mut client, inbound, outbound, mut server) = proxied_connection().await;
+        let forward =
+            tokio::spawn(async move { splice_bidirectional(&inbound, &outbound).await.unwrap() });
+
+        // When
+        client.write_all(b"ping").await.unwrap();
+        client.shutdown().await.unwrap();
+        let mut request = Vec::new();
+        server.read_to_end(&mut request).await.unwrap();
+        server.write_all(b"pong!").await.unwrap();
+        server.shutdown().await.unwrap();
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).await.unwrap();
+
+        // Then
+        assert_eq!(request, b"ping");
+        assert_eq!(response, b"pong!");
+        assert_eq!(forward.await.unwrap(), (4, 5));
+    }
+
+    /// CPU time of the process, user and system.
+    fn cpu_time() -> Duration {
+        // SAFETY: getrusage only writes to the given struct
+        let usage = unsafe {
+            let mut usage = std::mem::zeroed::<libc::rusage>();
+            libc::getrusage(libc::RUSAGE_SELF, &mut usage);
+            usage
+        };
+        let to_duration = |time: libc::timeval| {
+            Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+        };
+        to_duration(usage.ru_utime) + to_duration(usage.ru_stime)
+    }
+
+    /// Sends `size` bytes through a proxied connection, returning the wall and CPU time taken.
+    async fn measure(size: usize, use_splice: bool) -> (Duration, Duration) {
+        let (mut client, mut inbound, mut outbound, mut server) = proxied_connection().await;
+        let started_at = Instant::now();
+        let cpu_at_start = cpu_time();
+
+        let forward = tokio::spawn(async move {
+            if use_splice {
+                splice_bidirectional(&inbound, &outbound).await.unwrap();
+            } else {
+                copy_bidirectional(&mut inbound, &mut outbound)
+                    .await
+                    .unwrap();
+            }
+        });
+        let send = tokio::spawn(async move {
+            let chunk = vec![0x42; 1 << 20];
+            for _ in 0..size / chunk.len() {
+                client.write_all(&chunk).await.unwrap();
+            }
+            client.shutdown().await.unwrap();
+            client
+        });
+        let mut buffer = vec![0; 1 << 20];
+        let mut received = 0;
+        loop {
+            let read = server.read(&mut buffer).await.unwrap();
+            if read == 0 {
+                break;
+            }
+            received += read;
+        }
+        server.shutdown().await.unwrap();
+        drop(send.await.unwrap());
+        forward.await.unwrap();
+
+        assert_eq!(received, size);
+        (started_at.elapsed(), cpu_time() - cpu_at_start)
+    }
+
+    /// Compares splice with `copy_bidirectional`, the client and backend of the benchmark account
+    /// for the same CPU time in both. Run with
+    /// `cargo test --release splice_benchmark -- --ignored --nocapture`.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[ignore]
+    async fn splice_benchmark() {
+        let size = 4 << 30;
+        for (name, use_splice) in [("copy_bidirectional", false), ("splice", true)] {
+            let (elapsed, cpu) = measure(size, use_splice).await;
+            let throughput = size as f64 / (1 << 20) as f64 / elapsed.as_secs_f64();
+            println!("{name}: {throughput:.0} MiB/s, {cpu:?} of CPU in {elapsed:?}");
+        }
+    }
+}
@@ -0,0 +1,637 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::os::fd::{AsFd, BorrowedFd};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use socket2::{Domain, Socket, Type};
+use tokio::io::{AsyncRead, AsyncWrite, Interest, ReadBuf};
+use tokio::net::{TcpListener, TcpSocket, TcpStream, UdpSocket, UnixListener, UnixStream};
+use tokio::time::sleep;
+use tracing::{debug, info, warn};
+
+use crate::backends::inherited::{take_inet_listener, take_unix_listener};
+use crate::backends::resolver::{resolver, srv_name};
+use crate::backends::socket_options::{
+    configure_datagram, configure_listener, configure_outbound, listen_backlog,
+};
+use crate::configuration::{ListenerConfig, OutboundConfig, SocketConfig, UnixSocketConfig};
+
+const UNIX_PREFIX: &str = "unix:";
+
+const MIN_ACCEPT_BACKOFF: Duration = Duration::from_millis(10);
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Path of a `unix:/path/to.sock` address.
+pub(crate) fn unix_path(address: &str) -> Option<&str> {
+    address.strip_prefix(UNIX_PREFIX)
+}
+
+/// Address of a client, Unix socket peers are local processes.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum PeerAddress {
+    Inet(SocketAddr),
+    Unix,
+}
+
+impl PeerAddress {
+    /// IP address of the client, none for Unix socket peers, which must not pass for local
+    /// network clients.
+    pub(crate) fn ip(&self) -> Option<IpAddr> {
+        match self {
+            PeerAddress::Inet(address) => Some(address.ip()),
+            PeerAddress::Unix => None,
+        }
+    }
+}
+
+impl From<SocketAddr> for PeerAddress {
+    fn from(address: SocketAddr) -> Self {
+        PeerAddress::Inet(address)
+    }
+}
+
+impl Display for PeerAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PeerAddress::Inet(address) => write!(f, "{}:{}", address.ip(), address.port()),
+            PeerAddress::Unix => write!(f, "unix"),
+        }
+    }
+}
+
+pub(crate) enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+/// Connects to a TCP address, to a `unix:` socket or to the servers of a `srv:` name.
+pub(crate) async fn connect(address: &str) -> io::Result<Stream> {
+    connect_from(address, &OutboundConfig::default(), PeerAddress::Unix).await
+}
+
+/// Local address of an outbound TCP connection.
+#[derive(Debug, Clone, Copy)]
+struct Source {
+    ip: IpAddr,
+    transparent: bool,
+}
+
+/// Connects to the target of a route, from its `bind_address` or, in transparent mode, from the
+/// address of the client.
+pub(crate) async fn connect_from(
+    address: &str,
+    outbound: &OutboundConfig,
+    client: PeerAddress,
+) -> io::Result<Stream> {
+    if let Some(path) = unix_path(address) {
+        return UnixStream::connect(path).await.map(Stream::Unix);
+    }
+
+    // Clients of a Unix socket have no address to connect from
+    let source = match client {
+        PeerAddress::Inet(client) if outbound.transparent => Some(Source {
+            ip: client.ip().to_canonical(),
+            transparent: true,
+        }),
+        _ => outbound.bind_address.map(|ip| Source {
+            ip,
+            transparent: false,
+        }),
+    };
+
+    let resolver = resolver();
+    let mut addresses = resolver.resolve(address).await?;
+    if let Some(source) = source {
+        addresses.retain(|address| address.is_ipv4() == source.ip.is_ipv4());
+    }
+
+    let options = outbound.socket.clone().unwrap_or_default();
+    let delay = resolver.happy_eyeballs_delay();
+    let (stream, resolved) = connect_any(addresses, source, &options, delay).await?;
+    if srv_name(address).is_some() {
+        info!("Resolved {address} to {resolved}");
+    } else {
+        debug!("Resolved {address} to {resolved}");
+    }
+    Ok(Stream::Tcp(stream))
+}
+
+/// Connects to the first address that accepts, starting the next attempt whenever the pending
+/// ones fail or take longer than `delay`, as in RFC 8305.
+async fn connect_any(
+    addresses: Vec<SocketAddr>,
+    source: Option<Source>,
+    options: &SocketConfig,
+    delay: Duration,
+) -> io::Result<(TcpStream, SocketAddr)> {
+    let mut pending = addresses.into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_error = None;
+
+    loop {
+        if let Some(address) = pending.next() {
+            attempts.push(async move { (address, connect_tcp(address, source, options).await) });
+        }
+        if attempts.is_empty() {
+            return Err(last_error.unwrap_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "no address to connect to")
+            }));
+        }
+
+        tokio::select! {
+            Some((address, result)) = attempts.next() => match result {
+                Ok(stream) => return Ok((stream, address)),
+                Err(err) => last_error = Some(err),
+            },
+            _ = sleep(delay), if pending.len() > 0 => {}
+        }
+    }
+}
+
+async fn connect_tcp(
+    address: SocketAddr,
+    source: Option<Source>,
+    options: &SocketConfig,
+) -> io::Result<TcpStream> {
+    let socket = Socket::new(Domain::for_address(address), Type::STREAM, None)?;
+    configure_outbound(&socket, options)?;
+    if let Some(source) = source {
+        if source.transparent {
+            set_transparent(&socket)?;
+        }
+        socket.bind(&SocketAddr::new(source.ip, 0).into())?;
+    }
+    socket.set_nonblocking(true)?;
+
+    TcpSocket::from_std_stream(socket.into())
+        .connect(address)
+        .await
+}
+
+#[cfg(target_os = "linux")]
+fn set_transparent(socket: &Socket) -> io::Result<()> {
+    socket.set_ip_transparent(true)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_transparent(_: &Socket) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "transparent mode is only supported on Linux",
+    ))
+}
+
+impl Stream {
+    /// Runs a non-blocking `operation` on the file descriptor of the stream once it is ready for
+    /// `interest`, waiting again whenever the operation would block.
+    pub(crate) async fn async_io<R>(
+        &self,
+        interest: Interest,
+        operation: impl FnMut() -> io::Result<R>,
+    ) -> io::Result<R> {
+        match self {
+            Stream::Tcp(stream) => stream.async_io(interest, operation).await,
+            Stream::Unix(stream) => stream.async_io(interest, operation).await,
+        }
+    }
+}
+
+impl AsFd for Stream {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        match self {
+            Stream::Tcp(stream) => stream.as_fd(),
+            Stream::Unix(stream) => stream.as_fd(),
+        }
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            Stream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            Stream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            Stream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            Stream::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+pub(crate) enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl Listener {
+    /// Binds a TCP address or a `unix:` socket of a server block.
+    pub(crate) async fn bind(address: &str, config: &ListenerConfig) -> io::Result<Self> {
+        match unix_path(address) {
+            Some(path) => Self::bind_unix(path, &config.unix_socket).await,
+            None => Self::bind_tcp(address, config).await,
+        }
+    }
+
+    async fn bind_tcp(address: &str, config: &ListenerConfig) -> io::Result<Self> {
+        let address = resolve(address).await?;
+        if let Some(socket) = take_inet_listener(address, Type::STREAM) {
+            debug!("Using the inherited socket of {address}");
+            configure_listener(&socket, &config.socket)?;
+            socket.set_nonblocking(true)?;
+            return TcpListener::from_std(socket.into()).map(Listener::Tcp);
+        }
+
+        let socket = Socket::new(Domain::for_address(address), Type::STREAM, None)?;
+        set_ipv6_only(&socket, address, config.ipv6_only)?;
+        socket.set_reuse_address(true)?;
+        configure_listener(&socket, &config.socket)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&address.into())?;
+        socket.listen(listen_backlog(&config.socket))?;
+
+        TcpListener::from_std(socket.into()).map(Listener::Tcp)
+    }
+
+    /// Binds a `unix:` socket with the permissions and stale socket cleanup of `unix_socket`.
+    async fn bind_unix(path: &str, unix_socket: &UnixSocketConfig) -> io::Result<Self> {
+        if let Some(socket) = take_unix_listener(path) {
+            debug!("Using the inherited socket of {path}");
+            socket.set_nonblocking(true)?;
+            return UnixListener::from_std(socket.into()).map(Listener::Unix);
+        }
+
+        if unix_socket.remove_stale {
+            remove_stale_socket(path).await?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+        if let Some(mode) = &unix_socket.mode {
+            let mode = u32::from_str_radix(mode, 8).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid socket mode {mode}"),
+                )
+            })?;
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        }
+
+        Ok(Listener::Unix(listener))
+    }
+
+    /// Accepts the next client, skipping the connections which fail while being accepted, and
+    /// waiting longer after each failure while the process runs out of resources, e.g. of file
+    /// descriptors. Returns the errors the listener cannot recover from.
+    pub(crate) async fn accept_next(&self) -> io::Result<(Stream, PeerAddress)> {
+        let mut delay = MIN_ACCEPT_BACKOFF;
+        loop {
+            let err = match self.accept().await {
+                Ok(accepted) => return Ok(accepted),
+                Err(err) => err,
+            };
+            match classify_accept_error(&err) {
+                AcceptFailure::Connection => debug!("Cannot accept a connection; error={err}"),
+                AcceptFailure::Resources => {
+                    warn!("Cannot accept a connection, retrying in {delay:?}; error={err}");
+                    sleep(delay).await;
+                    delay = (delay * 2).min(MAX_ACCEPT_BACKOFF);
+                }
+                AcceptFailure::Listener => return Err(err),
+            }
+        }
+    }
+
+    pub(crate) async fn accept(&self) -> io::Result<(Stream, PeerAddress)> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, address) = listener.accept().await?;
+                Ok((Stream::Tcp(stream), PeerAddress::Inet(address)))
+            }
+            Listener::Unix(listener) => {
+                let (stream, _) = listener.accept().await?;
+                Ok((Stream::Unix(stream), PeerAddress::Unix))
+            }
+        }
+    }
+}
+
+impl AsFd for Listener {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        match self {
+            Listener::Tcp(listener) => listener.as_fd(),
+            Listener::Unix(listener) => listener.as_fd(),
+        }
+    }
+}
+
+/// Cause of a failure to accept a connection. See accept(2).
+#[derive(Debug, PartialEq)]
+enum AcceptFailure {
+    /// The connection being accepted failed, the next one can be accepted right away.
+    Connection,
+    /// The process or the system lacks resources, which may be released later.
+    Resources,
+    /// The listener cannot accept anymore.
+    Listener,
+}
+
+fn classify_accept_error(err: &io::Error) -> AcceptFailure {
+    match err.raw_os_error() {
+        Some(libc::EMFILE | libc::ENFILE | libc::ENOBUFS | libc::ENOMEM) => {
+            AcceptFailure::Resources
+        }
+        Some(
+            libc::ECONNABORTED
+            | libc::ECONNRESET
+            | libc::EINTR
+            | libc::EAGAIN
+            | libc::EPROTO
+            | libc::EPERM
+            | libc::ENETDOWN
+            | libc::ENOPROTOOPT
+            | libc::EHOSTDOWN
+            | libc::EHOSTUNREACH
+            | libc::EOPNOTSUPP
+            | libc::ENETUNREACH
+            | libc::ETIMEDOUT,
+        ) => AcceptFailure::Connection,
+        #[cfg(target_os = "linux")]
+        Some(libc::ENONET) => AcceptFailure::Connection,
+        _ => AcceptFailure::Listener,
+    }
+}
+
+/// Binds a UDP socket of a server block.
+pub(crate) async fn bind_udp(address: &str, config: &ListenerConfig) -> io::Result<UdpSocket> {
+    let address = resolve(address).await?;
+    if let Some(socket) = take_inet_listener(address, Type::DGRAM) {
+        debug!("Using the inherited socket of {address}");
+        configure_datagram(&socket, &config.socket)?;
+        socket.set_nonblocking(true)?;
+        return UdpSocket::from_std(socket.into());
+    }
+
+    let socket = Socket::new(Domain::for_address(address), Type::DGRAM, None)?;
+    set_ipv6_only(&socket, address, config.ipv6_only)?;
+    configure_datagram(&socket, &config.socket)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&address.into())?;
+
+    UdpSocket::from_std(socket.into())
+}
+
+async fn resolve(address: &str) -> io::Result<SocketAddr> {
+    tokio::net::lookup_host(address)
+        .await?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address to listen on"))
+}
+
+/// IPv6 sockets also accept IPv4 clients unless `ipv6_only`, the system default applies if unset.
+fn set_ipv6_only(socket: &Socket, address: SocketAddr, ipv6_only: Option<bool>) -> io::Result<()> {
+    match (address, ipv6_only) {
+        (SocketAddr::V6(_), Some(ipv6_only)) => socket.set_only_v6(ipv6_only),
+        _ => Ok(()),
+    }
+}
+
+/// Removes the socket file left by a previous process, unless another process still listens on it.
+pub(crate) async fn remove_stale_socket(path: &str) -> io::Result<()> {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{path} exists and is not a socket"),
+        ));
+    }
+
+    match UnixStream::connect(path).await {
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("another process is listening on {path}"),
+        )),
+        Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => {
+            info!("Removing stale socket {path}");
+            fs::remove_file(path)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::ListenAddresses;
+    use std::net::Ipv4Addr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn listener_config(address: &str) -> ListenerConfig {
+        ListenerConfig {
+            name: None,
+            listen: ListenAddresses(vec![address.to_string()]),
+            ipv6_only: None,
+            unix_socket: UnixSocketConfig::default(),
+            socket: SocketConfig::default(),
+            acceptors: 1,
+        }
+    }
+
+    fn socket_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("proxy-{}-{name}.sock", std::process::id()))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn should_exchange_over_unix_socket() {
+        // Given
+        let path = socket_path("exchange");
+        let address = format!("unix:{path}");
+        let listener = Listener::bind(&address, &listener_config(&address))
+            .await
+            .unwrap();
+
+        // When
+        let mut client = connect(&address).await.unwrap();
+        client.write_all(b"ping").await.unwrap();
+        let (mut server, peer) = listener.accept().await.unwrap();
+        let mut buffer = [0; 4];
+        server.read_exact(&mut buffer).await.unwrap();
+
+        // Then
+        assert_eq!(&buffer, b"ping");
+        assert_eq!(peer.ip(), None);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_connect_to_the_next_address_while_one_is_pending() {
+        // Given
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let reachable = listener.local_addr().unwrap();
+        let unreachable = SocketAddr::from(([192, 0, 2, 1], 25565));
+
+        // When
+        let result = connect_any(
+            vec![unreachable, reachable],
+            None,
+            &SocketConfig::default(),
+            Duration::from_millis(50),
+        )
+        .await;
+
+        // Then
+        let (_, address) = result.unwrap();
+        assert_eq!(address, reachable);
+    }
+
+    #[tokio::test]
+    async fn should_connect_from_the_bind_address() {
+        // Given
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let outbound = OutboundConfig {
+            bind_address: Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2))),
+            transparent: false,
+            socket: None,
+        };
+
+        // When
+        connect_from(&address, &outbound, PeerAddress::Unix)
+            .await
+            .unwrap();
+        let (_, peer) = listener.accept().await.unwrap();
+
+        // Then
+        assert_eq!(peer.ip(), IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2)));
+    }
+
+    #[tokio::test]
+    async fn should_bind_several_acceptors_to_one_address() {
+        // Given
+        let mut config = listener_config("127.0.0.1:0");
+        config.socket.reuse_port = true;
+        let Listener::Tcp(first) = Listener::bind("127.0.0.1:0", &config).await.unwrap() else {
+            panic!("expected a TCP listener");
+        };
+        let address = first.local_addr().unwrap().to_string();
+
+        // When
+        let second = Listener::bind(&address, &config).await;
+
+        // Then
+        assert!(second.is_ok());
+    }
+
+    #[tokio::test]
+    async fn should_accept_only_ipv6_clients() {
+        // Given
+        let mut config = listener_config("[::]:0");
+        config.ipv6_only = Some(true);
+        let Listener::Tcp(listener) = Listener::bind("[::]:0", &config).await.unwrap() else {
+            panic!("expected a TCP listener");
+        };
+        let port = listener.local_addr().unwrap().port();
+
+        // When
+        let ipv4 = TcpStream::connect(("127.0.0.1", port)).await;
+        let ipv6 = TcpStream::connect(("::1", port)).await;
+
+        // Then
+        assert!(ipv4.is_err());
+        assert!(ipv6.is_ok());
+    }
+
+    #[tokio::test]
+    async fn should_replace_stale_socket_and_set_mode() {
+        // Given
+        let path = socket_path("stale");
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let address = format!("unix:{path}");
+        let mut config = listener_config(&address);
+        config.unix_socket.mode = Some("600".to_string());
+
+        // When
+        let listener = Listener::bind(&address, &config).await;
+
+        // Then
+        assert!(listener.is_ok());
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_not_replace_socket_in_use() {
+        // Given
+        let path = socket_path("in-use");
+        let address = format!("unix:{path}");
+        let _listener = Listener::bind(&address, &listener_config(&address))
+            .await
+            .unwrap();
+
+        // When
+        let result = Listener::bind(&address, &listener_config(&address)).await;
+
+        // Then
+        assert!(result.is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_classify_accept_errors() {
+        // Given
+        let out_of_files = io::Error::from_raw_os_error(libc::EMFILE);
+        let aborted = io::Error::from_raw_os_error(libc::ECONNABORTED);
+        let closed_listener = io::Error::from_raw_os_error(libc::EBADF);
+
+        // When
+        let failures = [&out_of_files, &aborted, &closed_listener].map(classify_accept_error);
+
+        // Then
+        assert_eq!(
+            failures,
+            [
+                AcceptFailure::Resources,
+                AcceptFailure::Connection,
+                AcceptFailure::Listener
+            ]
+        );
+    }
+}
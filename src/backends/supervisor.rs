@@ -0,0 +1,383 @@
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::io;
+use std::os::fd::{AsFd, OwnedFd};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use tokio::net::UdpSocket;
+use tokio::sync::watch;
+use tokio::time::sleep;
+use tracing::{debug, error, info, warn};
+
+use crate::backends::stream::{bind_udp, Listener};
+use crate::configuration::{ListenerConfig, RestartPolicy, SupervisorConfig};
+
+/// Listeners of every server block, in the order they were started.
+static LISTENERS: Mutex<Vec<Arc<ListenerHealth>>> = Mutex::new(Vec::new());
+
+static SUPERVISOR: OnceLock<SupervisorConfig> = OnceLock::new();
+
+/// Set once the listeners are handed over to another process.
+static STOPPED: OnceLock<watch::Sender<bool>> = OnceLock::new();
+
+/// Connections accepted by the listeners which are not closed yet.
+static ACTIVE_CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Interval of the checks for the end of the connections while draining.
+const DRAIN_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+pub(crate) fn configure_supervisor(config: SupervisorConfig) {
+    if SUPERVISOR.set(config).is_err() {
+        warn!("The supervisor is already configured");
+    }
+}
+
+fn supervisor_config() -> &'static SupervisorConfig {
+    SUPERVISOR.get_or_init(SupervisorConfig::default)
+}
+
+/// Socket bound on an address of a server block.
+pub(crate) trait BindListener: AsFd + Sized + Send + 'static {
+    fn bind(
+        address: &str,
+        config: &ListenerConfig,
+    ) -> impl Future<Output = io::Result<Self>> + Send;
+}
+
+impl BindListener for Listener {
+    fn bind(
+        address: &str,
+        config: &ListenerConfig,
+    ) -> impl Future<Output = io::Result<Self>> + Send {
+        Listener::bind(address, config)
+    }
+}
+
+impl BindListener for UdpSocket {
+    fn bind(
+        address: &str,
+        config: &ListenerConfig,
+    ) -> impl Future<Output = io::Result<Self>> + Send {
+        bind_udp(address, config)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ListenerState {
+    Starting,
+    Listening,
+    Restarting(String),
+    Failed(String),
+    /// Handed over to another process, or no longer serving.
+    Stopped,
+}
+
+impl Display for ListenerState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ListenerState::Starting => write!(f, "starting"),
+            ListenerState::Listening => write!(f, "listening"),
+            ListenerState::Restarting(error) => write!(f, "restarting ({error})"),
+            ListenerState::Failed(error) => write!(f, "failed ({error})"),
+            ListenerState::Stopped => write!(f, "stopped"),
+        }
+    }
+}
+
+pub(crate) struct ListenerHealth {
+    name: String,
+    address: String,
+    state: watch::Sender<ListenerState>,
+    /// Copy of the bound socket, to hand it over to another process.
+    socket: Mutex<Option<OwnedFd>>,
+}
+
+impl ListenerHealth {
+    fn new(name: &str, address: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            address: address.to_string(),
+            state: watch::Sender::new(ListenerState::Starting),
+            socket: Mutex::new(None),
+        }
+    }
+
+    /// Adds a listener to the ones reported by the status, before it is first bound.
+    pub(crate) fn register(name: &str, address: &str) -> Arc<Self> {
+        let health = Arc::new(Self::new(name, address));
+        LISTENERS.lock().unwrap().push(Arc::clone(&health));
+        health
+    }
+
+    pub(crate) fn state(&self) -> ListenerState {
+        self.state.borrow().clone()
+    }
+
+    fn set_state(&self, state: ListenerState) {
+        self.state.send_replace(state);
+    }
+
+    fn set_socket(&self, socket: Option<&impl AsFd>) {
+        let socket = socket.and_then(|socket| socket.as_fd().try_clone_to_owned().ok());
+        *self.socket.lock().unwrap() = socket;
+    }
+}
+
+/// Copies of the sockets of every bound listener.
+pub(crate) fn listening_sockets() -> Vec<OwnedFd> {
+    LISTENERS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|health| health.socket.lock().unwrap().as_ref()?.try_clone().ok())
+        .collect()
+}
+
+fn stopped_sender() -> &'static watch::Sender<bool> {
+    STOPPED.get_or_init(|| watch::Sender::new(false))
+}
+
+/// Stops every listener for good, the connections they accepted go on.
+pub(crate) fn stop_listeners() {
+    stopped_sender().send_replace(true);
+}
+
+async fn stopped() {
+    // The sender is static, it is never dropped
+    let _ = stopped_sender()
+        .subscribe()
+        .wait_for(|stopped| *stopped)
+        .await;
+}
+
+/// Waits for the listeners to be stopped, then for the end of the connections they accepted and
+/// of their UDP sessions.
+pub(crate) async fn drain() {
+    stopped().await;
+    loop {
+        let active = ACTIVE_CONNECTIONS.load(Ordering::Relaxed);
+        if active == 0 {
+            info!("Every connection is closed");
+            return;
+        }
+        debug!("Draining {active} connections");
+        sleep(DRAIN_CHECK_INTERVAL).await;
+    }
+}
+
+/// Counts a connection as active until it is dropped.
+pub(crate) struct ActiveConnection(());
+
+impl ActiveConnection {
+    pub(crate) fn track() -> Self {
+        ACTIVE_CONNECTIONS.fetch_add(1, Ordering::Relaxed);
+        Self(())
+    }
+}
+
+impl Drop for ActiveConnection {
+    fn drop(&mut self) {
+        ACTIVE_CONNECTIONS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Health of the proxy, `healthy` when every listener is listening and `degraded` otherwise,
+/// followed by the state of every listener, one per line.
+pub(crate) fn listeners_status() -> String {
+    let listeners = LISTENERS.lock().unwrap();
+    let listening = listeners
+        .iter()
+        .filter(|health| health.state() == ListenerState::Listening)
+        .count();
+    let summary = if listening == listeners.len() {
+        "healthy"
+    } else {
+        "degraded"
+    };
+
+    std::iter::once(format!(
+        "{summary} {listening}/{} listening",
+        listeners.len()
+    ))
+    .chain(
+        listeners
+            .iter()
+            .map(|health| format!("{} {} {}", health.name, health.address, health.state())),
+    )
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+/// Waits for the first bind of every listener, returning the listeners which failed with their
+/// error.
+pub(crate) async fn bind_failures() -> Vec<String> {
+    let listeners = LISTENERS.lock().unwrap().clone();
+    let mut failures = Vec::new();
+
+    for health in listeners {
+        let mut state = health.state.subscribe();
+        let state = match state
+            .wait_for(|state| *state != ListenerState::Starting)
+            .await
+        {
+            Ok(state) => state.clone(),
+            Err(_) => continue,
+        };
+        if let ListenerState::Restarting(err) | ListenerState::Failed(err) = state {
+            failures.push(format!("{} {}: {err}", health.name, health.address));
+        }
+    }
+    failures
+}
+
+/// Binds `address` and serves it, binding it again whenever binding fails or serving stops on an
+/// error the listener cannot recover from, as set by the restart policy.
+pub(crate) async fn supervise<S, F, Fut>(
+    health: Arc<ListenerHealth>,
+    config: ListenerConfig,
+    serve: F,
+) where
+    S: BindListener,
+    F: Fn(S) -> Fut,
+    Fut: Future<Output = io::Result<()>>,
+{
+    let address = health.address.clone();
+    supervise_with(&health, &address, &config, supervisor_config(), serve).await;
+}
+
+async fn supervise_with<S, F, Fut>(
+    health: &ListenerHealth,
+    address: &str,
+    config: &ListenerConfig,
+    supervisor: &SupervisorConfig,
+    serve: F,
+) where
+    S: BindListener,
+    F: Fn(S) -> Fut,
+    Fut: Future<Output = io::Result<()>>,
+{
+    let min_delay = Duration::from_millis(supervisor.restart_delay_ms);
+    let max_delay = Duration::from_millis(supervisor.max_restart_delay_ms).max(min_delay);
+    let mut delay = min_delay;
+
+    loop {
+        let mut listening_since = None;
+        let result = match S::bind(address, config).await {
+            Ok(socket) => {
+                info!("Listening on: {}", address);
+                health.set_state(ListenerState::Listening);
+                health.set_socket(Some(&socket));
+                listening_since = Some(Instant::now());
+                tokio::select! {
+                    result = serve(socket) => result,
+                    _ = stopped() => {
+                        health.set_state(ListenerState::Stopped);
+                        Ok(())
+                    }
+                }
+            }
+            Err(err) => Err(err),
+        };
+        health.set_socket(None::<&S>);
+
+        let Err(err) = result else {
+            health.set_state(ListenerState::Stopped);
+            return;
+        };
+        if supervisor.restart == RestartPolicy::Never {
+            error!(
+                "error with {} listener on {address}, not restarting; error={err}",
+                health.name
+            );
+            health.set_state(ListenerState::Failed(err.to_string()));
+            return;
+        }
+
+        // A listener which served for a while is not failing in a loop, it starts over
+        if listening_since.is_some_and(|since| since.elapsed() >= max_delay) {
+            delay = min_delay;
+        }
+        error!(
+            "error with {} listener on {address}, restarting in {delay:?}; error={err}",
+            health.name
+        );
+        health.set_state(ListenerState::Restarting(err.to_string()));
+        sleep(delay).await;
+        delay = (delay * 2).min(max_delay);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::{ListenAddresses, SocketConfig, UnixSocketConfig};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn listener_config() -> ListenerConfig {
+        ListenerConfig {
+            name: None,
+            listen: ListenAddresses(vec!["127.0.0.1:0".to_string()]),
+            ipv6_only: None,
+            unix_socket: UnixSocketConfig::default(),
+            socket: SocketConfig::default(),
+            acceptors: 1,
+        }
+    }
+
+    fn supervisor_config(restart: RestartPolicy) -> SupervisorConfig {
+        SupervisorConfig {
+            restart,
+            restart_delay_ms: 10,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn should_restart_listener_after_fatal_error() {
+        // Given
+        let health = ListenerHealth::new("TCP", "127.0.0.1:0");
+        let runs = AtomicUsize::new(0);
+
+        // When
+        supervise_with(
+            &health,
+            "127.0.0.1:0",
+            &listener_config(),
+            &supervisor_config(RestartPolicy::Always),
+            |_: Listener| async {
+                match runs.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(io::Error::from_raw_os_error(libc::EBADF)),
+                    _ => Ok(()),
+                }
+            },
+        )
+        .await;
+
+        // Then
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        assert_eq!(health.state(), ListenerState::Stopped);
+    }
+
+    #[tokio::test]
+    async fn should_leave_listener_failed_without_restart() {
+        // Given
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = taken.local_addr().unwrap().to_string();
+        let health = ListenerHealth::new("TCP", &address);
+
+        // When
+        supervise_with(
+            &health,
+            &address,
+            &listener_config(),
+            &supervisor_config(RestartPolicy::Never),
+            |_: Listener| async { Ok(()) },
+        )
+        .await;
+
+        // Then
+        assert!(matches!(health.state(), ListenerState::Failed(_)));
+    }
+}
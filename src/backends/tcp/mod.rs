@@ -1,13 +1,16 @@
+use crate::backends::start_listeners;
+use crate::configuration::{ListenerConfig, OutboundConfig};
 use tokio::task::JoinHandle;
-use tracing::error;
 
 mod tcp_proxy;
 
-pub(crate) fn start_tcp_proxy(listen: String, redirect: String) -> JoinHandle<()> {
-    tokio::spawn(async move {
-        let proxy = tcp_proxy::start_tcp_proxy(listen, redirect).await;
-        if let Err(err) = proxy {
-            error!("error with TCP proxy; error={err}");
-        }
+pub(crate) fn start_tcp_proxy(
+    listener: ListenerConfig,
+    redirect: String,
+    mut outbound: OutboundConfig,
+) -> JoinHandle<()> {
+    outbound.inherit_socket(&listener.socket);
+    start_listeners("TCP", listener, move |socket| {
+        tcp_proxy::listen(socket, redirect.clone(), outbound.clone())
     })
 }
@@ -1,26 +1,35 @@
-use std::error::Error;
+use std::io;
 
 use crate::backends::proxy_connection::proxy_connection;
-use tokio::net::TcpListener;
-use tracing::{error, info};
+use crate::backends::stream::Listener;
+use crate::backends::supervisor::ActiveConnection;
+use crate::configuration::OutboundConfig;
+use tracing::error;
 
-pub(crate) async fn start_tcp_proxy(
-    listen_address: String,
+pub(crate) async fn listen(
+    listener: Listener,
     server_address: String,
-) -> Result<(), Box<dyn Error>> {
-    let listener = TcpListener::bind(&listen_address).await?;
-    info!("Listening on: {}", listen_address);
-
-    while let Ok((mut inbound, address)) = listener.accept().await {
+    outbound: OutboundConfig,
+) -> io::Result<()> {
+    loop {
+        let (mut inbound, address) = listener.accept_next().await?;
+        let connection = ActiveConnection::track();
         let server_address = server_address.clone();
+        let outbound = outbound.clone();
         tokio::spawn(async move {
-            if let Err(err) =
-                proxy_connection("tcp", &mut inbound, address, &server_address, None).await
+            let _connection = connection;
+            if let Err(err) = proxy_connection(
+                "tcp",
+                &mut inbound,
+                address,
+                &server_address,
+                &outbound,
+                None,
+            )
+            .await
             {
                 error!("{err}");
             }
         });
     }
-
-    Ok(())
 }
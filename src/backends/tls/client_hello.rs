@@ -0,0 +1,296 @@
+use thiserror::Error;
+
+const HANDSHAKE_RECORD: u8 = 0x16;
+const CLIENT_HELLO: u8 = 0x01;
+const SERVER_NAME_EXTENSION: u16 = 0x0000;
+const ALPN_EXTENSION: u16 = 0x0010;
+const HOST_NAME_TYPE: u8 = 0x00;
+
+#[derive(Error, Debug, PartialEq)]
+pub(crate) enum ClientHelloError {
+    #[error("the client hello is incomplete")]
+    Incomplete,
+    #[error("the client did not start with a TLS handshake")]
+    NotHandshake,
+    #[error("the first handshake message is not a client hello")]
+    NotClientHello,
+    #[error("the client hello is malformed")]
+    Malformed,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct ClientHello {
+    pub(crate) server_name: Option<String>,
+    pub(crate) alpn: Vec<String>,
+}
+
+/// Parses the ClientHello from the first bytes sent by the client, which may span several TLS
+/// records. Returns `ClientHelloError::Incomplete` when more bytes must be read.
+pub(crate) fn parse_client_hello(bytes: &[u8]) -> Result<ClientHello, ClientHelloError> {
+    let handshake = read_handshake_message(bytes)?;
+    if handshake[0] != CLIENT_HELLO {
+        return Err(ClientHelloError::NotClientHello);
+    }
+
+    let mut reader = Reader::new(&handshake[4..]);
+    reader.skip(2)?; // Legacy version
+    reader.skip(32)?; // Random
+    let session_id_length = reader.read_u8()? as usize;
+    reader.skip(session_id_length)?;
+    let cipher_suites_length = reader.read_u16()? as usize;
+    reader.skip(cipher_suites_length)?;
+    let compression_methods_length = reader.read_u8()? as usize;
+    reader.skip(compression_methods_length)?;
+
+    let mut client_hello = ClientHello {
+        server_name: None,
+        alpn: Vec::new(),
+    };
+
+    // Extensions are optional in old clients
+    if reader.is_empty() {
+        return Ok(client_hello);
+    }
+
+    let extensions_length = reader.read_u16()? as usize;
+    let mut extensions = Reader::new(reader.read(extensions_length)?);
+    while !extensions.is_empty() {
+        let extension_type = extensions.read_u16()?;
+        let extension_length = extensions.read_u16()? as usize;
+        let mut extension = Reader::new(extensions.read(extension_length)?);
+
+        match extension_type {
+            SERVER_NAME_EXTENSION => client_hello.server_name = read_server_name(&mut extension)?,
+            ALPN_EXTENSION => client_hello.alpn = read_alpn(&mut extension)?,
+            _ => {}
+        }
+    }
+
+    Ok(client_hello)
+}
+
+/// Concatenates the payload of the handshake records until the first message is complete.
+fn read_handshake_message(bytes: &[u8]) -> Result<Vec<u8>, ClientHelloError> {
+    let mut records = Reader::new(bytes);
+    let mut handshake = Vec::new();
+
+    loop {
+        let content_type = records
+            .read_u8()
+            .map_err(|_| ClientHelloError::Incomplete)?;
+        if content_type != HANDSHAKE_RECORD {
+            return Err(ClientHelloError::NotHandshake);
+        }
+
+        records.skip(2).map_err(|_| ClientHelloError::Incomplete)?; // Record version
+        let length = records
+            .read_u16()
+            .map_err(|_| ClientHelloError::Incomplete)? as usize;
+        let fragment = records
+            .read(length)
+            .map_err(|_| ClientHelloError::Incomplete)?;
+        handshake.extend_from_slice(fragment);
+
+        if handshake.len() >= 4 {
+            let message_length = Reader::new(&handshake[1..4]).read_u24()? as usize;
+            if handshake.len() >= 4 + message_length {
+                handshake.truncate(4 + message_length);
+                return Ok(handshake);
+            }
+        }
+    }
+}
+
+fn read_server_name(extension: &mut Reader) -> Result<Option<String>, ClientHelloError> {
+    let list_length = extension.read_u16()? as usize;
+    let mut names = Reader::new(extension.read(list_length)?);
+
+    while !names.is_empty() {
+        let name_type = names.read_u8()?;
+        let name_length = names.read_u16()? as usize;
+        let name = names.read(name_length)?;
+
+        if name_type == HOST_NAME_TYPE {
+            let name = std::str::from_utf8(name).map_err(|_| ClientHelloError::Malformed)?;
+            return Ok(Some(name.to_string()));
+        }
+    }
+
+    Ok(None)
+}
+
+fn read_alpn(extension: &mut Reader) -> Result<Vec<String>, ClientHelloError> {
+    let list_length = extension.read_u16()? as usize;
+    let mut protocols = Reader::new(extension.read(list_length)?);
+    let mut alpn = Vec::new();
+
+    while !protocols.is_empty() {
+        let protocol_length = protocols.read_u8()? as usize;
+        let protocol = protocols.read(protocol_length)?;
+        alpn.push(String::from_utf8_lossy(protocol).to_string());
+    }
+
+    Ok(alpn)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    index: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, index: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.index >= self.bytes.len()
+    }
+
+    fn read(&mut self, length: usize) -> Result<&'a [u8], ClientHelloError> {
+        let bytes = self
+            .bytes
+            .get(self.index..self.index + length)
+            .ok_or(ClientHelloError::Malformed)?;
+        self.index += length;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, length: usize) -> Result<(), ClientHelloError> {
+        self.read(length).map(|_| ())
+    }
+
+    fn read_u8(&mut self) -> Result<u8, ClientHelloError> {
+        Ok(self.read(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, ClientHelloError> {
+        let bytes = self.read(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn read_u24(&mut self) -> Result<u32, ClientHelloError> {
+        let bytes = self.read(3)?;
+        Ok(u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_u16_length(bytes: &[u8]) -> Vec<u8> {
+        [&(bytes.len() as u16).to_be_bytes(), bytes].concat()
+    }
+
+    fn client_hello_message(server_name: Option<&str>, alpn: &[&str]) -> Vec<u8> {
+        let mut extensions = Vec::new();
+        if let Some(server_name) = server_name {
+            let name = [
+                &[HOST_NAME_TYPE][..],
+                &with_u16_length(server_name.as_bytes()),
+            ]
+            .concat();
+            extensions.extend_from_slice(&SERVER_NAME_EXTENSION.to_be_bytes());
+            extensions.extend_from_slice(&with_u16_length(&with_u16_length(&name)));
+        }
+        if !alpn.is_empty() {
+            let protocols = alpn
+                .iter()
+                .flat_map(|protocol| [&[protocol.len() as u8][..], protocol.as_bytes()].concat())
+                .collect::<Vec<_>>();
+            extensions.extend_from_slice(&ALPN_EXTENSION.to_be_bytes());
+            extensions.extend_from_slice(&with_u16_length(&with_u16_length(&protocols)));
+        }
+
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[0x42; 32]);
+        body.push(0x00); // Session id
+        body.extend_from_slice(&with_u16_length(&[0x13, 0x01]));
+        body.extend_from_slice(&[0x01, 0x00]); // Compression methods
+        body.extend_from_slice(&with_u16_length(&extensions));
+
+        let length = (body.len() as u32).to_be_bytes();
+        [&[CLIENT_HELLO, length[1], length[2], length[3]][..], &body].concat()
+    }
+
+    fn record(fragment: &[u8]) -> Vec<u8> {
+        [
+            &[HANDSHAKE_RECORD, 0x03, 0x01][..],
+            &with_u16_length(fragment),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn should_parse_server_name_and_alpn() {
+        // Given
+        let bytes = record(&client_hello_message(
+            Some("example.com"),
+            &["h2", "http/1.1"],
+        ));
+
+        // When
+        let client_hello = parse_client_hello(&bytes).unwrap();
+
+        // Then
+        assert_eq!(
+            client_hello,
+            ClientHello {
+                server_name: Some("example.com".to_string()),
+                alpn: vec!["h2".to_string(), "http/1.1".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn should_parse_client_hello_without_server_name() {
+        // Given
+        let bytes = record(&client_hello_message(None, &[]));
+
+        // When
+        let client_hello = parse_client_hello(&bytes).unwrap();
+
+        // Then
+        assert_eq!(client_hello.server_name, None);
+        assert!(client_hello.alpn.is_empty());
+    }
+
+    #[test]
+    fn should_parse_client_hello_fragmented_in_records() {
+        // Given
+        let message = client_hello_message(Some("example.com"), &[]);
+        let (first, second) = message.split_at(20);
+        let bytes = [record(first), record(second)].concat();
+
+        // When
+        let client_hello = parse_client_hello(&bytes).unwrap();
+
+        // Then
+        assert_eq!(client_hello.server_name, Some("example.com".to_string()));
+    }
+
+    #[test]
+    fn should_ask_for_more_bytes() {
+        // Given
+        let bytes = record(&client_hello_message(Some("example.com"), &[]));
+
+        // When
+        let result = parse_client_hello(&bytes[..bytes.len() - 1]);
+
+        // Then
+        assert_eq!(result, Err(ClientHelloError::Incomplete));
+    }
+
+    #[test]
+    fn should_reject_other_protocols() {
+        // Given
+        let bytes = b"GET / HTTP/1.1\r\n";
+
+        // When
+        let result = parse_client_hello(bytes);
+
+        // Then
+        assert_eq!(result, Err(ClientHelloError::NotHandshake));
+    }
+}
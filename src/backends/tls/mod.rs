@@ -0,0 +1,42 @@
+use crate::backends::host_table::HostTable;
+use crate::backends::start_listeners;
+use crate::configuration::{ListenerConfig, OutboundConfig, TlsHost};
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+
+mod client_hello;
+mod tls_proxy;
+
+/// Backend of a hostname, only chosen when the client offers `alpn` if set.
+pub(crate) struct TlsRoute {
+    target: String,
+    alpn: Option<String>,
+    outbound: OutboundConfig,
+}
+
+impl TlsRoute {
+    fn accepts(&self, offered_alpn: &[String]) -> bool {
+        self.alpn
+            .as_ref()
+            .is_none_or(|alpn| offered_alpn.contains(alpn))
+    }
+}
+
+pub(crate) fn start_tls_proxy(listener: ListenerConfig, hosts: Vec<TlsHost>) -> JoinHandle<()> {
+    // Hosts sharing a hostname are tried in order of declaration
+    let routes = HostTable::grouped(hosts.into_iter().map(|mut host| {
+        host.outbound.inherit_socket(&listener.socket);
+        let route = TlsRoute {
+            target: host.target,
+            alpn: host.alpn,
+            outbound: host.outbound,
+        };
+        (host.hostname, route)
+    }));
+
+    let routes = Arc::new(routes);
+
+    start_listeners("TLS", listener, move |socket| {
+        tls_proxy::listen(socket, Arc::clone(&routes))
+    })
+}
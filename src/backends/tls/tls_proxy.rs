@@ -0,0 +1,132 @@
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::AsyncReadExt;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::timeout;
+use tracing::{debug, error};
+
+use crate::backends::host_table::HostTable;
+use crate::backends::proxy_connection::{proxy_connection, ProxyConnectionError};
+use crate::backends::stream::{Listener, PeerAddress, Stream};
+use crate::backends::supervisor::ActiveConnection;
+use crate::backends::tls::client_hello::{parse_client_hello, ClientHello, ClientHelloError};
+use crate::backends::tls::TlsRoute;
+
+const CLIENT_HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_CLIENT_HELLO_SIZE: usize = 64 * 1024;
+
+#[derive(Error, Debug)]
+enum TlsRedirectError {
+    #[error("could not read client hello; error={0}")]
+    ReadFailed(std::io::Error),
+    #[error("client hello was not received in time")]
+    Timeout,
+    #[error("connection closed before the end of the client hello")]
+    ConnectionClosed,
+    #[error("client hello is too large")]
+    TooLarge,
+    #[error("invalid client hello; error={0}")]
+    InvalidClientHello(ClientHelloError),
+    #[error("no route for server name {0}")]
+    UnknownHost(String),
+    #[error("{0}")]
+    ProxyError(ProxyConnectionError),
+}
+
+pub(crate) async fn listen(
+    listener: Listener,
+    hosts: Arc<HostTable<Vec<TlsRoute>>>,
+) -> io::Result<()> {
+    loop {
+        let (inbound, address) = listener.accept_next().await?;
+        let connection = ActiveConnection::track();
+        debug!("Accepted new client {}", address);
+        let hosts_ref = Arc::clone(&hosts);
+
+        tokio::spawn(async move {
+            let _connection = connection;
+            if let Err(err) = redirect(inbound, address, hosts_ref).await {
+                error!("tls:{} {err}", address);
+            }
+        });
+    }
+}
+
+async fn redirect(
+    mut inbound: Stream,
+    address: PeerAddress,
+    hosts: Arc<HostTable<Vec<TlsRoute>>>,
+) -> Result<(), TlsRedirectError> {
+    let mut buffer = Vec::new();
+    let client_hello = timeout(
+        CLIENT_HELLO_TIMEOUT,
+        read_client_hello(&mut inbound, &mut buffer),
+    )
+    .await
+    .map_err(|_| TlsRedirectError::Timeout)??;
+
+    // Clients without SNI only match the default host
+    let routes = match &client_hello.server_name {
+        Some(server_name) => hosts.get(server_name),
+        None => hosts.get("*"),
+    };
+    let route = routes
+        .and_then(|routes| {
+            routes
+                .iter()
+                .find(|route| route.accepts(&client_hello.alpn))
+        })
+        .ok_or_else(|| {
+            TlsRedirectError::UnknownHost(
+                client_hello
+                    .server_name
+                    .clone()
+                    .unwrap_or_else(|| "(none)".to_string()),
+            )
+        })?;
+
+    debug!(
+        "TLS client hello from {}; server_name={:?} alpn={:?}",
+        address, client_hello.server_name, client_hello.alpn
+    );
+
+    proxy_connection(
+        "tls",
+        &mut inbound,
+        address,
+        &route.target,
+        &route.outbound,
+        Some(&buffer),
+    )
+    .await
+    .map_err(TlsRedirectError::ProxyError)
+}
+
+/// Reads from the client until the ClientHello is complete, keeping every byte read to replay it to
+/// the backend.
+async fn read_client_hello<S: AsyncRead + AsyncWrite + Unpin>(
+    inbound: &mut S,
+    buffer: &mut Vec<u8>,
+) -> Result<ClientHello, TlsRedirectError> {
+    let mut chunk = [0; 4096];
+
+    loop {
+        let read = inbound
+            .read(&mut chunk)
+            .await
+            .map_err(TlsRedirectError::ReadFailed)?;
+        if read == 0 {
+            return Err(TlsRedirectError::ConnectionClosed);
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+
+        match parse_client_hello(buffer) {
+            Ok(client_hello) => return Ok(client_hello),
+            Err(ClientHelloError::Incomplete) if buffer.len() < MAX_CLIENT_HELLO_SIZE => {}
+            Err(ClientHelloError::Incomplete) => return Err(TlsRedirectError::TooLarge),
+            Err(err) => return Err(TlsRedirectError::InvalidClientHello(err)),
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use crate::backends::start_listeners;
+use crate::configuration::ListenerConfig;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+pub(crate) mod session;
+mod udp_proxy;
+
+pub(crate) fn start_udp_proxy(
+    listener: ListenerConfig,
+    redirect: String,
+    idle_timeout_seconds: u64,
+    max_sessions: usize,
+) -> JoinHandle<()> {
+    let idle_timeout = Duration::from_secs(idle_timeout_seconds);
+
+    start_listeners("UDP", listener, move |socket| {
+        udp_proxy::listen(socket, redirect.clone(), idle_timeout, max_sessions)
+    })
+}
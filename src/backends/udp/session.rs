@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+use std::mem;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::net::UdpSocket;
+use tokio::sync::Notify;
+use tokio::time::timeout;
+use tracing::{debug, error, info};
+
+use crate::backends::proxy_connection::traffic;
+use crate::backends::resolver::resolver;
+use crate::backends::supervisor::ActiveConnection;
+
+const MAX_DATAGRAM_SIZE: usize = 65535;
+
+/// Datagrams of a client kept while its session opens.
+const MAX_BUFFERED_DATAGRAMS: usize = 16;
+
+/// Upstream socket of a client address.
+pub(crate) struct Session {
+    upstream: UdpSocket,
+    last_activity: Mutex<Instant>,
+    sent: AtomicU64,
+    received: AtomicU64,
+    closed: Notify,
+}
+
+impl Session {
+    async fn send(&self, datagram: &[u8]) -> std::io::Result<()> {
+        self.touch();
+        self.upstream.send(datagram).await?;
+        self.sent
+            .fetch_add(datagram.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn touch(&self) {
+        *self.last_activity.lock().unwrap() = Instant::now();
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last_activity.lock().unwrap().elapsed()
+    }
+}
+
+/// Session of a client, the id telling it from a later session of the same client.
+struct Slot {
+    id: u64,
+    state: SlotState,
+}
+
+enum SlotState {
+    /// The session is being opened, the datagrams are sent once it is.
+    Opening(Vec<Vec<u8>>),
+    Open(Arc<Session>),
+}
+
+/// Sessions of the clients of a listening socket. Datagrams of the backend are sent back to the
+/// client until no datagram is exchanged in either direction for the idle timeout.
+#[derive(Clone)]
+pub(crate) struct Sessions {
+    protocol: &'static str,
+    socket: Arc<UdpSocket>,
+    idle_timeout: Duration,
+    max_sessions: usize,
+    reply_filter: fn(&[u8]) -> bool,
+    sessions: Arc<Mutex<HashMap<SocketAddr, Slot>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Sessions {
+    pub(crate) fn new(
+        protocol: &'static str,
+        socket: Arc<UdpSocket>,
+        idle_timeout: Duration,
+        max_sessions: usize,
+    ) -> Self {
+        Self {
+            protocol,
+            socket,
+            idle_timeout,
+            max_sessions,
+            reply_filter: |_| true,
+            sessions: Arc::default(),
+            next_id: Arc::default(),
+        }
+    }
+
+    /// Only the datagrams of the backend accepted by `reply_filter` are sent back to the client.
+    pub(crate) fn with_reply_filter(mut self, reply_filter: fn(&[u8]) -> bool) -> Self {
+        self.reply_filter = reply_filter;
+        self
+    }
+
+    /// Sends a datagram of the client to its session, or keeps it while the session opens.
+    /// Returns whether the client has a session.
+    pub(crate) async fn forward(&self, address: SocketAddr, datagram: &[u8]) -> bool {
+        let session = match self.sessions.lock().unwrap().get_mut(&address) {
+            Some(Slot {
+                state: SlotState::Open(session),
+                ..
+            }) => Arc::clone(session),
+            Some(Slot {
+                state: SlotState::Opening(datagrams),
+                ..
+            }) => {
+                if datagrams.len() < MAX_BUFFERED_DATAGRAMS {
+                    datagrams.push(datagram.to_vec());
+                }
+                return true;
+            }
+            None => return false,
+        };
+
+        if let Err(err) = session.send(datagram).await {
+            debug!("could not forward datagram of {address}; error={err}");
+        }
+        true
+    }
+
+    /// Opens a session of the client to `server_address` in the background, sending `datagrams`
+    /// once it is open. Clients beyond the maximum number of sessions are dropped.
+    pub(crate) fn open(
+        &self,
+        address: SocketAddr,
+        server_address: String,
+        datagrams: Vec<Vec<u8>>,
+    ) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        {
+            let mut sessions = self.sessions.lock().unwrap();
+            if sessions.len() >= self.max_sessions {
+                debug!(
+                    "{}:{address} dropped, the maximum of {} sessions is reached",
+                    self.protocol, self.max_sessions
+                );
+                return;
+            }
+            let state = SlotState::Opening(datagrams);
+            sessions.insert(address, Slot { id, state });
+        }
+
+        // Sessions keep a previous process alive after an upgrade, until they are idle
+        let connection = ActiveConnection::track();
+        let sessions = self.clone();
+        tokio::spawn(async move {
+            let _connection = connection;
+            info!(
+                "{}:connection from {} forwarded to {}",
+                sessions.protocol, address, server_address,
+            );
+
+            let session = match connect(&server_address).await {
+                Ok(upstream) => Arc::new(Session {
+                    upstream,
+                    last_activity: Mutex::new(Instant::now()),
+                    sent: AtomicU64::new(0),
+                    received: AtomicU64::new(0),
+                    closed: Notify::new(),
+                }),
+                Err(err) => {
+                    sessions.remove(address, id);
+                    error!(
+                        "{}:{address} could not open session to {server_address}; error={err}",
+                        sessions.protocol
+                    );
+                    return;
+                }
+            };
+
+            let buffered = match sessions.sessions.lock().unwrap().get_mut(&address) {
+                Some(slot) if slot.id == id => {
+                    mem::replace(&mut slot.state, SlotState::Open(Arc::clone(&session)))
+                }
+                // Closed while opening
+                _ => return,
+            };
+            if let SlotState::Opening(datagrams) = buffered {
+                for datagram in datagrams {
+                    if let Err(err) = session.send(&datagram).await {
+                        debug!("could not forward datagram of {address}; error={err}");
+                    }
+                }
+            }
+
+            sessions.reply(&session, address).await;
+            sessions.remove(address, id);
+            let sent = session.sent.load(Ordering::Relaxed);
+            let received = session.received.load(Ordering::Relaxed);
+            traffic().record(sent, received);
+            debug!(
+                "{}:connection from {} closed; sent={} received={}",
+                sessions.protocol, address, sent, received,
+            );
+        });
+    }
+
+    /// Closes the session of the client, if any, for its next datagrams to open a new one.
+    pub(crate) fn close(&self, address: SocketAddr) {
+        let slot = self.sessions.lock().unwrap().remove(&address);
+        if let Some(Slot {
+            state: SlotState::Open(session),
+            ..
+        }) = slot
+        {
+            session.closed.notify_one();
+        }
+    }
+
+    fn remove(&self, address: SocketAddr, id: u64) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.get(&address).is_some_and(|slot| slot.id == id) {
+            sessions.remove(&address);
+        }
+    }
+
+    /// Sends the datagrams of the backend back to the client until the session is idle or
+    /// closed.
+    async fn reply(&self, session: &Session, address: SocketAddr) {
+        let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+
+        loop {
+            let remaining = self.idle_timeout.saturating_sub(session.idle_for());
+            if remaining.is_zero() {
+                return;
+            }
+
+            let received = tokio::select! {
+                received = timeout(remaining, session.upstream.recv(&mut buffer)) => received,
+                _ = session.closed.notified() => return,
+            };
+            match received {
+                Ok(Ok(length)) => {
+                    session.touch();
+                    if !(self.reply_filter)(&buffer[..length]) {
+                        continue;
+                    }
+                    match self.socket.send_to(&buffer[..length], address).await {
+                        Ok(sent) => {
+                            session.received.fetch_add(sent as u64, Ordering::Relaxed);
+                        }
+                        Err(err) => debug!("could not reply to {address}; error={err}"),
+                    }
+                }
+                Ok(Err(err)) => {
+                    // The backend is not listening, the client may retry later
+                    debug!("could not receive datagram from backend; error={err}");
+                    return;
+                }
+                // The client may have sent datagrams in the meantime
+                Err(_) => {}
+            }
+        }
+    }
+}
+
+/// Binds an upstream socket connected to the first address of `server_address`.
+async fn connect(server_address: &str) -> std::io::Result<UdpSocket> {
+    let target = resolver()
+        .resolve(server_address)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "no address for the target")
+        })?;
+    let local_address: SocketAddr = match target {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let upstream = UdpSocket::bind(local_address).await?;
+    upstream.connect(target).await?;
+    Ok(upstream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn should_open_a_new_session_after_closing() {
+        // Given
+        let backend = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let sessions = Sessions::new("udp", socket, Duration::from_secs(60), 16);
+        let client: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        let backend_address = backend.local_addr().unwrap().to_string();
+        sessions.open(client, backend_address.clone(), vec![b"first".to_vec()]);
+        let mut buffer = [0; 16];
+        let (_, first_upstream) = backend.recv_from(&mut buffer).await.unwrap();
+
+        // When
+        sessions.close(client);
+        let forwarded = sessions.forward(client, b"dropped").await;
+        sessions.open(client, backend_address, vec![b"second".to_vec()]);
+        let (length, second_upstream) = backend.recv_from(&mut buffer).await.unwrap();
+
+        // Then
+        assert!(!forwarded);
+        assert_eq!(&buffer[..length], b"second");
+        assert_ne!(first_upstream, second_upstream);
+    }
+}
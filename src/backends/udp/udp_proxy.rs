@@ -0,0 +1,141 @@
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+use tracing::debug;
+
+use crate::backends::udp::session::Sessions;
+
+const MAX_DATAGRAM_SIZE: usize = 65535;
+
+pub(crate) async fn listen(
+    socket: UdpSocket,
+    server_address: String,
+    idle_timeout: Duration,
+    max_sessions: usize,
+) -> io::Result<()> {
+    forward(Arc::new(socket), server_address, idle_timeout, max_sessions).await;
+
+    Ok(())
+}
+
+async fn forward(
+    socket: Arc<UdpSocket>,
+    server_address: String,
+    idle_timeout: Duration,
+    max_sessions: usize,
+) {
+    let sessions = Sessions::new("udp", Arc::clone(&socket), idle_timeout, max_sessions);
+    let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+
+    loop {
+        let (length, address) = match socket.recv_from(&mut buffer).await {
+            Ok(received) => received,
+            Err(err) => {
+                // Errors such as ICMP port unreachable only concern a single datagram
+                debug!("could not receive datagram; error={err}");
+                continue;
+            }
+        };
+
+        let datagram = &buffer[..length];
+        if !sessions.forward(address, datagram).await {
+            sessions.open(address, server_address.clone(), vec![datagram.to_vec()]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use tokio::time::timeout;
+
+    async fn start_echo_backend() -> SocketAddr {
+        let backend = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = backend.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buffer = [0; 1024];
+            while let Ok((length, address)) = backend.recv_from(&mut buffer).await {
+                backend.send_to(&buffer[..length], address).await.unwrap();
+            }
+        });
+        address
+    }
+
+    async fn start_proxy(idle_timeout: Duration, max_sessions: usize) -> SocketAddr {
+        let backend_address = start_echo_backend().await;
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = socket.local_addr().unwrap();
+        tokio::spawn(forward(
+            Arc::new(socket),
+            backend_address.to_string(),
+            idle_timeout,
+            max_sessions,
+        ));
+        address
+    }
+
+    async fn exchange(client: &UdpSocket, datagram: &[u8]) -> Vec<u8> {
+        client.send(datagram).await.unwrap();
+        let mut buffer = [0; 1024];
+        let length = timeout(Duration::from_secs(1), client.recv(&mut buffer))
+            .await
+            .unwrap()
+            .unwrap();
+        buffer[..length].to_vec()
+    }
+
+    #[tokio::test]
+    async fn should_forward_datagrams_both_ways() {
+        // Given
+        let proxy_address = start_proxy(Duration::from_secs(60), 16).await;
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(proxy_address).await.unwrap();
+
+        // When
+        let first = exchange(&client, b"ping").await;
+        let second = exchange(&client, b"pong").await;
+
+        // Then
+        assert_eq!(first, b"ping");
+        assert_eq!(second, b"pong");
+    }
+
+    #[tokio::test]
+    async fn should_open_new_session_after_idle_timeout() {
+        // Given
+        let proxy_address = start_proxy(Duration::from_millis(100), 16).await;
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(proxy_address).await.unwrap();
+        exchange(&client, b"first").await;
+
+        // When
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let response = exchange(&client, b"second").await;
+
+        // Then
+        assert_eq!(response, b"second");
+    }
+
+    #[tokio::test]
+    async fn should_drop_clients_beyond_the_maximum_of_sessions() {
+        // Given
+        let proxy_address = start_proxy(Duration::from_secs(60), 1).await;
+        let first = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        first.connect(proxy_address).await.unwrap();
+        let second = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        second.connect(proxy_address).await.unwrap();
+        exchange(&first, b"first").await;
+
+        // When
+        second.send(b"second").await.unwrap();
+        let mut buffer = [0; 1024];
+        let response = timeout(Duration::from_millis(300), second.recv(&mut buffer)).await;
+
+        // Then
+        assert!(response.is_err());
+        assert_eq!(exchange(&first, b"again").await, b"again");
+    }
+}
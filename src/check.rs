@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::io;
+use std::time::Duration;
+
+use futures::future::join_all;
+
+use crate::backends::resolver::{configure_resolver, resolver};
+use crate::backends::stream::connect;
+use crate::configuration::validation::{targets, Diagnostic, Protocol, Target};
+use crate::configuration::{load_config, ConfigError};
+
+/// Time given to a target to accept a connection before it is reported as unreachable.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Validates the configuration file at `path`, then tries to reach the targets of its routes,
+/// printing the problems. Returns whether the proxy can start with it: unreachable targets are
+/// only warnings, as they may come up after the proxy.
+pub(crate) async fn check_config(path: &str) -> bool {
+    let (config, sources) = match load_config(path) {
+        Ok(loaded) => loaded,
+        Err(err @ ConfigError::CannotRead { .. }) => {
+            println!("error: {err}");
+            return false;
+        }
+        Err(err) => {
+            println!("{err}\n\n{path} is invalid");
+            return false;
+        }
+    };
+
+    configure_resolver(config.dns.clone());
+    // Hosts of included files take the protocol of the server block routing them
+    let protocols = config
+        .servers
+        .iter()
+        .filter_map(|server| Some((server.listener().name.clone()?, server.protocol())))
+        .collect::<HashMap<_, _>>();
+    let mut unreachable = 0;
+    for source in &sources {
+        let warnings = unreachable_targets(&source.contents, &protocols).await;
+        for warning in &warnings {
+            println!("{}\n", warning.render(&source.path, &source.contents));
+        }
+        unreachable += warnings.len();
+    }
+    match unreachable {
+        0 => println!("{path} is valid"),
+        count => println!("{path} is valid, with {count} unreachable targets"),
+    }
+    true
+}
+
+async fn unreachable_targets(
+    contents: &str,
+    protocols: &HashMap<String, Protocol>,
+) -> Vec<Diagnostic> {
+    let checks = targets(contents, protocols)
+        .into_iter()
+        .map(|target| async move {
+            let result = reach(&target).await;
+            (target, result)
+        });
+    join_all(checks)
+        .await
+        .into_iter()
+        .filter_map(|(target, result)| {
+            let err = result.err()?;
+            Some(Diagnostic::warning(
+                format!("target {} is unreachable: {err}", target.address),
+                target.span,
+            ))
+        })
+        .collect()
+}
+
+/// Connects to a TCP target, UDP targets are only resolved as nothing tells they listen.
+async fn reach(target: &Target) -> io::Result<()> {
+    let attempt = async {
+        match target.protocol {
+            Protocol::Tcp => connect(&target.address).await.map(drop),
+            Protocol::Udp => resolver().resolve(&target.address).await.map(drop),
+        }
+    };
+    tokio::time::timeout(CONNECT_TIMEOUT, attempt)
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connection timed out"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[tokio::test]
+    async fn should_warn_about_unreachable_targets() {
+        // Given
+        let reachable = TcpListener::bind("127.0.0.1:0").unwrap();
+        let unreachable = TcpListener::bind("127.0.0.1:0").unwrap();
+        let unreachable_address = unreachable.local_addr().unwrap();
+        drop(unreachable);
+        let contents = format!(
+            r#"
+[[servers]]
+listen = "0.0.0.0:25565"
+hosts = [
+    {{ hostname = "up.example.com", target = "{}" }},
+    {{ hostname = "down.example.com", target = "{unreachable_address}" }},
+]
+"#,
+            reachable.local_addr().unwrap()
+        );
+
+        // When
+        let warnings = unreachable_targets(&contents, &HashMap::new()).await;
+
+        // Then
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0]
+            .message
+            .starts_with(&format!("target {unreachable_address} is unreachable")));
+        assert!(warnings[0]
+            .render("config.toml", &contents)
+            .contains("--> config.toml:6:"));
+    }
+}
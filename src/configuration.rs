@@ -1,51 +1,798 @@
 use std::fmt::Debug;
-use std::fs::File;
-use std::io::Read;
+use std::fs;
+use std::io;
+use std::net::IpAddr;
 
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use thiserror::Error;
 
+use environment::substitute_env;
+use include::include_files;
+use server_type::{deserialize_servers, with_server_kinds};
+use validation::{validate, Diagnostic, Protocol, Severity};
+
+mod environment;
+mod include;
+mod server_type;
+pub(crate) mod validation;
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub(crate) struct MaintenanceConfig {
+    #[serde(default)]
+    pub(crate) enabled: bool,
+    pub(crate) motd: Option<String>,
+    pub(crate) version: Option<String>,
+    pub(crate) kick_message: Option<String>,
+    #[serde(default)]
+    pub(crate) bypass: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct AggregateStatusConfig {
+    #[serde(default)]
+    pub(crate) targets: Vec<String>,
+    #[serde(default = "default_aggregate_cache_seconds")]
+    pub(crate) cache_seconds: u64,
+}
+
+fn default_aggregate_cache_seconds() -> u64 {
+    5
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct StatusCacheConfig {
+    #[serde(default = "default_status_ttl_seconds")]
+    pub(crate) ttl_seconds: u64,
+    #[serde(default = "default_status_max_stale_seconds")]
+    pub(crate) max_stale_seconds: u64,
+    pub(crate) fallback_motd: Option<String>,
+    pub(crate) fallback_version: Option<String>,
+}
+
+fn default_status_ttl_seconds() -> u64 {
+    5
+}
+
+fn default_status_max_stale_seconds() -> u64 {
+    60
+}
+
+impl Default for StatusCacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl_seconds: default_status_ttl_seconds(),
+            max_stale_seconds: default_status_max_stale_seconds(),
+            fallback_motd: None,
+            fallback_version: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub(crate) struct StatusOverrideConfig {
+    pub(crate) motd: Option<String>,
+    pub(crate) favicon: Option<String>,
+    pub(crate) version_name: Option<String>,
+    pub(crate) version_protocol: Option<i32>,
+    #[serde(default)]
+    pub(crate) hide_players: bool,
+    #[serde(default)]
+    pub(crate) hide_sample: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct UnixSocketConfig {
+    pub(crate) mode: Option<String>,
+    #[serde(default = "default_remove_stale")]
+    pub(crate) remove_stale: bool,
+}
+
+fn default_remove_stale() -> bool {
+    true
+}
+
+impl Default for UnixSocketConfig {
+    fn default() -> Self {
+        Self {
+            mode: None,
+            remove_stale: default_remove_stale(),
+        }
+    }
+}
+
+/// Caching of the records of the targets, the TTL of the records is bounded by the min and max.
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct DnsConfig {
+    #[serde(default)]
+    pub(crate) min_ttl_seconds: u64,
+    #[serde(default = "default_dns_max_ttl_seconds")]
+    pub(crate) max_ttl_seconds: u64,
+    #[serde(default = "default_dns_max_stale_seconds")]
+    pub(crate) max_stale_seconds: u64,
+    #[serde(default = "default_happy_eyeballs_delay_ms")]
+    pub(crate) happy_eyeballs_delay_ms: u64,
+}
+
+pub(crate) fn default_dns_max_ttl_seconds() -> u64 {
+    300
+}
+
+fn default_dns_max_stale_seconds() -> u64 {
+    3600
+}
+
+fn default_happy_eyeballs_delay_ms() -> u64 {
+    250
+}
+
+impl Default for DnsConfig {
+    fn default() -> Self {
+        Self {
+            min_ttl_seconds: 0,
+            max_ttl_seconds: default_dns_max_ttl_seconds(),
+            max_stale_seconds: default_dns_max_stale_seconds(),
+            happy_eyeballs_delay_ms: default_happy_eyeballs_delay_ms(),
+        }
+    }
+}
+
+/// What to do with a listener which failed.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum RestartPolicy {
+    /// Bind the listener again after a delay, doubling after each consecutive failure.
+    #[default]
+    Always,
+    /// Leave the listener stopped.
+    Never,
+}
+
+/// Restarting of the listeners which fail to bind or stop on a fatal error.
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct SupervisorConfig {
+    #[serde(default)]
+    pub(crate) restart: RestartPolicy,
+    #[serde(default = "default_restart_delay_ms")]
+    pub(crate) restart_delay_ms: u64,
+    #[serde(default = "default_max_restart_delay_ms")]
+    pub(crate) max_restart_delay_ms: u64,
+    /// Exits with an error when a listener cannot be bound at startup.
+    #[serde(default)]
+    pub(crate) exit_on_bind_failure: bool,
+}
+
+fn default_restart_delay_ms() -> u64 {
+    1000
+}
+
+fn default_max_restart_delay_ms() -> u64 {
+    60000
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        Self {
+            restart: RestartPolicy::default(),
+            restart_delay_ms: default_restart_delay_ms(),
+            max_restart_delay_ms: default_max_restart_delay_ms(),
+            exit_on_bind_failure: false,
+        }
+    }
+}
+
+/// A single address or a list of addresses.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(from = "OneOrMany")]
+pub(crate) struct ListenAddresses(pub(crate) Vec<String>);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl From<OneOrMany> for ListenAddresses {
+    fn from(addresses: OneOrMany) -> Self {
+        match addresses {
+            OneOrMany::One(address) => ListenAddresses(vec![address]),
+            OneOrMany::Many(addresses) => ListenAddresses(addresses),
+        }
+    }
+}
+
+/// Options of the TCP sockets of a server block, or of the connections to the target of a route.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub(crate) struct SocketConfig {
+    pub(crate) nodelay: Option<bool>,
+    pub(crate) keepalive_seconds: Option<u64>,
+    #[serde(default)]
+    pub(crate) reuse_port: bool,
+    pub(crate) send_buffer_size: Option<usize>,
+    pub(crate) recv_buffer_size: Option<usize>,
+    pub(crate) backlog: Option<i32>,
+    pub(crate) fastopen: Option<bool>,
+    pub(crate) splice: Option<bool>,
+}
+
+impl SocketConfig {
+    /// Options of a route, with those it does not set taken from `defaults`.
+    fn merged_with(self, defaults: &SocketConfig) -> SocketConfig {
+        SocketConfig {
+            nodelay: self.nodelay.or(defaults.nodelay),
+            keepalive_seconds: self.keepalive_seconds.or(defaults.keepalive_seconds),
+            reuse_port: self.reuse_port,
+            send_buffer_size: self.send_buffer_size.or(defaults.send_buffer_size),
+            recv_buffer_size: self.recv_buffer_size.or(defaults.recv_buffer_size),
+            backlog: self.backlog.or(defaults.backlog),
+            fastopen: self.fastopen.or(defaults.fastopen),
+            splice: self.splice.or(defaults.splice),
+        }
+    }
+}
+
+/// Addresses a server block listens on, all of them share the routes of the block.
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct ListenerConfig {
+    /// Names the listeners of the block in the logs and the status, instead of the kind of server.
+    pub(crate) name: Option<String>,
+    pub(crate) listen: ListenAddresses,
+    pub(crate) ipv6_only: Option<bool>,
+    #[serde(default)]
+    pub(crate) unix_socket: UnixSocketConfig,
+    #[serde(default)]
+    pub(crate) socket: SocketConfig,
+    #[serde(default = "default_acceptors")]
+    pub(crate) acceptors: usize,
+}
+
+fn default_acceptors() -> usize {
+    1
+}
+
+/// Source address of the connections to the target of a route. In transparent mode, the
+/// connections come from the address of the client.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub(crate) struct OutboundConfig {
+    pub(crate) bind_address: Option<IpAddr>,
+    #[serde(default)]
+    pub(crate) transparent: bool,
+    pub(crate) socket: Option<SocketConfig>,
+}
+
+impl OutboundConfig {
+    /// Uses the socket options of the server block for those the route does not set, option by
+    /// option.
+    pub(crate) fn inherit_socket(&mut self, socket: &SocketConfig) {
+        self.socket = Some(match self.socket.take() {
+            Some(own) => own.merged_with(socket),
+            None => socket.clone(),
+        });
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct QueryConfig {
+    pub(crate) listen: String,
+    pub(crate) hostname: Option<String>,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub(crate) struct Host {
     pub(crate) hostname: String,
     pub(crate) target: String,
+    pub(crate) whitelist: Option<String>,
+    pub(crate) banned_players: Option<String>,
+    pub(crate) maintenance: Option<MaintenanceConfig>,
+    pub(crate) aggregate_status: Option<AggregateStatusConfig>,
+    pub(crate) status_cache: Option<StatusCacheConfig>,
+    pub(crate) status_override: Option<StatusOverrideConfig>,
+    #[serde(flatten)]
+    pub(crate) outbound: OutboundConfig,
 }
 
 #[derive(Deserialize, Debug, Clone)]
-#[serde(untagged)]
+pub(crate) struct TlsHost {
+    pub(crate) hostname: String,
+    pub(crate) target: String,
+    pub(crate) alpn: Option<String>,
+    #[serde(flatten)]
+    pub(crate) outbound: OutboundConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct HttpHost {
+    pub(crate) hostname: String,
+    pub(crate) target: String,
+    pub(crate) path_prefix: Option<String>,
+    #[serde(flatten)]
+    pub(crate) outbound: OutboundConfig,
+}
+
+fn default_udp_idle_timeout_seconds() -> u64 {
+    60
+}
+
+/// Each session has its own upstream socket, the limit keeps spoofed clients from using every
+/// file descriptor.
+fn default_udp_max_sessions() -> usize {
+    1024
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct BedrockHost {
+    pub(crate) server_address: String,
+    pub(crate) target: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct BedrockPongConfig {
+    #[serde(default = "default_bedrock_motd")]
+    pub(crate) motd: String,
+    pub(crate) sub_motd: Option<String>,
+    #[serde(default = "default_bedrock_version")]
+    pub(crate) version: String,
+    #[serde(default = "default_bedrock_protocol")]
+    pub(crate) protocol: i32,
+    #[serde(default)]
+    pub(crate) online_players: u32,
+    #[serde(default = "default_bedrock_max_players")]
+    pub(crate) max_players: u32,
+}
+
+fn default_bedrock_motd() -> String {
+    "McProxy".to_string()
+}
+
+fn default_bedrock_version() -> String {
+    "1.21.0".to_string()
+}
+
+fn default_bedrock_protocol() -> i32 {
+    685
+}
+
+fn default_bedrock_max_players() -> u32 {
+    20
+}
+
+impl Default for BedrockPongConfig {
+    fn default() -> Self {
+        Self {
+            motd: default_bedrock_motd(),
+            sub_motd: None,
+            version: default_bedrock_version(),
+            protocol: default_bedrock_protocol(),
+            online_players: 0,
+            max_players: default_bedrock_max_players(),
+        }
+    }
+}
+
+/// Server block, of which the kind is given by `type`, read by [`server_type::TypedServer`].
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
 pub(crate) enum Servers {
-    Minecraft { listen: String, hosts: Vec<Host> },
-    Tcp { listen: String, redirect: String },
+    Minecraft {
+        #[serde(flatten)]
+        listener: ListenerConfig,
+        hosts: Vec<Host>,
+        query: Option<QueryConfig>,
+    },
+    Tcp {
+        #[serde(flatten)]
+        listener: ListenerConfig,
+        redirect: String,
+        #[serde(flatten)]
+        outbound: OutboundConfig,
+    },
+    Tls {
+        #[serde(flatten)]
+        listener: ListenerConfig,
+        hosts: Vec<TlsHost>,
+    },
+    Http {
+        #[serde(flatten)]
+        listener: ListenerConfig,
+        hosts: Vec<HttpHost>,
+        #[serde(default)]
+        forwarded_headers: bool,
+    },
+    Udp {
+        #[serde(flatten)]
+        listener: ListenerConfig,
+        redirect: String,
+        #[serde(default = "default_udp_idle_timeout_seconds")]
+        idle_timeout_seconds: u64,
+        #[serde(default = "default_udp_max_sessions")]
+        max_sessions: usize,
+    },
+    Bedrock {
+        #[serde(flatten)]
+        listener: ListenerConfig,
+        hosts: Vec<BedrockHost>,
+        #[serde(default)]
+        pong: BedrockPongConfig,
+        #[serde(default = "default_udp_idle_timeout_seconds")]
+        idle_timeout_seconds: u64,
+        #[serde(default = "default_udp_max_sessions")]
+        max_sessions: usize,
+    },
+}
+
+impl Servers {
+    pub(crate) fn listener(&self) -> &ListenerConfig {
+        match self {
+            Servers::Minecraft { listener, .. }
+            | Servers::Tcp { listener, .. }
+            | Servers::Tls { listener, .. }
+            | Servers::Http { listener, .. }
+            | Servers::Udp { listener, .. }
+            | Servers::Bedrock { listener, .. } => listener,
+        }
+    }
+
+    pub(crate) fn protocol(&self) -> Protocol {
+        match self {
+            Servers::Udp { .. } | Servers::Bedrock { .. } => Protocol::Udp,
+            _ => Protocol::Tcp,
+        }
+    }
+
+    /// Adds a host to the routes of the block, unless the block already routes its hostname.
+    fn add_host(&mut self, host: toml::Table) -> Result<(), String> {
+        match self {
+            Servers::Minecraft { hosts, .. } => {
+                add_route(hosts, host, |host: &Host| host.hostname.to_lowercase())
+            }
+            Servers::Tls { hosts, .. } => add_route(hosts, host, |host: &TlsHost| {
+                route_name(&host.hostname, host.alpn.as_deref())
+            }),
+            Servers::Http { hosts, .. } => add_route(hosts, host, |host: &HttpHost| {
+                route_name(&host.hostname, host.path_prefix.as_deref())
+            }),
+            Servers::Bedrock { hosts, .. } => add_route(hosts, host, |host: &BedrockHost| {
+                host.server_address.clone()
+            }),
+            Servers::Tcp { .. } | Servers::Udp { .. } => {
+                Err("it has no hosts, as it redirects every client".to_string())
+            }
+        }
+    }
 }
 
+fn add_route<H: DeserializeOwned>(
+    hosts: &mut Vec<H>,
+    host: toml::Table,
+    route: impl Fn(&H) -> String,
+) -> Result<(), String> {
+    let host = toml::Value::Table(host)
+        .try_into::<H>()
+        .map_err(|err| err.message().to_string())?;
+    let name = route(&host);
+    if hosts.iter().any(|existing| route(existing) == name) {
+        return Err(format!("duplicate hostname {name}, already routed"));
+    }
+    hosts.push(host);
+    Ok(())
+}
+
+/// Hostname of a route, along with what tells it apart from the other routes of the hostname.
+pub(crate) fn route_name(hostname: &str, discriminant: Option<&str>) -> String {
+    match discriminant {
+        Some(discriminant) => format!("{} ({discriminant})", hostname.to_lowercase()),
+        None => hostname.to_lowercase(),
+    }
+}
+
+fn default_control_socket_mode() -> String {
+    "600".to_string()
+}
+
+/// Version of the configuration format read by this proxy, `version` defaults to it.
+pub(crate) const CONFIG_VERSION: i64 = 1;
+
 #[derive(Deserialize, Debug, Clone)]
 pub(crate) struct Config {
+    #[serde(deserialize_with = "deserialize_servers")]
     pub(crate) servers: Vec<Servers>,
+    pub(crate) whitelist: Option<String>,
+    pub(crate) banned_players: Option<String>,
+    pub(crate) control_socket: Option<String>,
+    /// Octal permissions of the control socket, which can take the listening sockets over.
+    #[serde(default = "default_control_socket_mode")]
+    pub(crate) control_socket_mode: String,
+    #[serde(default)]
+    pub(crate) dns: DnsConfig,
+    #[serde(default)]
+    pub(crate) supervisor: SupervisorConfig,
+    /// Files adding server blocks and hosts, such as `conf.d/*.toml`, relative to this file.
+    #[serde(default)]
+    pub(crate) include: Vec<String>,
 }
 
-#[derive(Error, Debug, PartialEq)]
-pub enum ConfigError {
-    #[error("error while parsing the file: {0}")]
-    ParseError(String),
-    #[error("cannot read file")]
-    CannotRead,
-    #[error("file is not found")]
-    FileNotFound,
-}
-
-pub(crate) fn read_config(config_file_name: String) -> Result<Config, ConfigError> {
-    let file = File::open(config_file_name);
-    if let Ok(mut file) = file {
-        let mut contents = String::new();
-        if file.read_to_string(&mut contents).is_ok() {
-            match toml::from_str::<Config>(&contents) {
-                Ok(config) => Ok(config),
-                Err(err) => Err(ConfigError::ParseError(err.message().to_string())),
-            }
-        } else {
-            Err(ConfigError::CannotRead)
-        }
-    } else {
-        Err(ConfigError::FileNotFound)
+#[derive(Error, Debug)]
+pub(crate) enum ConfigError {
+    #[error("cannot read {path}: {source}")]
+    CannotRead {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+    /// Problems of the file, rendered with the lines they point at.
+    #[error("{}", .0.join("\n\n"))]
+    Invalid(Vec<String>),
+}
+
+/// Configuration file, with the environment variables of its values substituted.
+#[derive(Debug, Clone)]
+pub(crate) struct ConfigSource {
+    pub(crate) path: String,
+    pub(crate) contents: String,
+}
+
+impl ConfigSource {
+    fn read(path: &str) -> Result<Self, ConfigError> {
+        let contents = fs::read_to_string(path).map_err(|source| ConfigError::CannotRead {
+            path: path.to_string(),
+            source,
+        })?;
+        let contents = substitute_env(&contents)
+            .map_err(|diagnostic| ConfigError::Invalid(vec![diagnostic.render(path, &contents)]))?;
+        Ok(Self {
+            path: path.to_string(),
+            contents,
+        })
+    }
+}
+
+pub(crate) fn read_config(path: &str) -> Result<Config, ConfigError> {
+    load_config(path).map(|(config, _)| config)
+}
+
+/// Reads the configuration file at `path` along with the files it includes, returned with it.
+pub(crate) fn load_config(path: &str) -> Result<(Config, Vec<ConfigSource>), ConfigError> {
+    let source = ConfigSource::read(path)?;
+    let mut config = parse_config::<Config>(&source.path, &source.contents)?;
+    let mut sources = vec![source];
+    include_files(&mut config, path, &mut sources)?;
+    Ok((config, sources))
+}
+
+/// Parses the contents of the file at `path`, then checks its addresses and hostnames.
+pub(crate) fn parse_config<T: DeserializeOwned>(
+    path: &str,
+    contents: &str,
+) -> Result<T, ConfigError> {
+    let config = with_server_kinds(contents, || toml::from_str::<T>(contents)).map_err(|err| {
+        ConfigError::Invalid(vec![Diagnostic::from_toml(&err).render(path, contents)])
+    })?;
+
+    let errors = validate(contents)
+        .into_iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .map(|diagnostic| diagnostic.render(path, contents))
+        .collect::<Vec<_>>();
+    if !errors.is_empty() {
+        return Err(ConfigError::Invalid(errors));
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_single_and_multiple_listen_addresses() {
+        // Given
+        let contents = r#"
+            [[servers]]
+            listen = "0.0.0.0:8080"
+            redirect = "127.0.0.1:80"
+
+            [[servers]]
+            listen = ["0.0.0.0:25565", "[::]:25565"]
+            ipv6_only = true
+            hosts = [{ hostname = "localhost", target = "127.0.0.1:25566" }]
+        "#;
+
+        // When
+        let config = parse_config::<Config>("config.toml", contents).unwrap();
+
+        // Then
+        let Servers::Tcp { listener, .. } = &config.servers[0] else {
+            panic!("expected a TCP server");
+        };
+        assert_eq!(listener.listen.0, vec!["0.0.0.0:8080"]);
+
+        let Servers::Minecraft { listener, .. } = &config.servers[1] else {
+            panic!("expected a Minecraft server");
+        };
+        assert_eq!(listener.listen.0, vec!["0.0.0.0:25565", "[::]:25565"]);
+        assert_eq!(listener.ipv6_only, Some(true));
+    }
+
+    #[test]
+    fn should_parse_outbound_options_of_routes() {
+        // Given
+        let contents = r#"
+            [[servers]]
+            listen = "0.0.0.0:8080"
+            redirect = "127.0.0.1:80"
+            transparent = true
+
+            [[servers]]
+            listen = "0.0.0.0:25565"
+            hosts = [{ hostname = "localhost", target = "127.0.0.1:25566", bind_address = "10.0.0.2" }]
+
+            [servers.socket]
+            nodelay = true
+        "#;
+
+        // When
+        let config = parse_config::<Config>("config.toml", contents).unwrap();
+
+        // Then
+        let Servers::Tcp { outbound, .. } = &config.servers[0] else {
+            panic!("expected a TCP server");
+        };
+        assert!(outbound.transparent);
+
+        let Servers::Minecraft {
+            listener, hosts, ..
+        } = &config.servers[1]
+        else {
+            panic!("expected a Minecraft server");
+        };
+        assert_eq!(hosts[0].outbound.bind_address, Some([10, 0, 0, 2].into()));
+        assert_eq!(listener.socket.nodelay, Some(true));
+    }
+
+    #[test]
+    fn should_merge_the_socket_options_of_a_route_into_those_of_the_block() {
+        // Given
+        let block = SocketConfig {
+            nodelay: Some(false),
+            keepalive_seconds: Some(60),
+            fastopen: Some(true),
+            ..Default::default()
+        };
+        let mut outbound = OutboundConfig {
+            socket: Some(SocketConfig {
+                nodelay: Some(true),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        // When
+        outbound.inherit_socket(&block);
+
+        // Then
+        let socket = outbound.socket.unwrap();
+        assert_eq!(socket.nodelay, Some(true));
+        assert_eq!(socket.keepalive_seconds, Some(60));
+        assert_eq!(socket.fastopen, Some(true));
+    }
+
+    #[test]
+    fn should_point_at_the_line_of_a_parse_error() {
+        // Given
+        let contents = "[[servers]]\nlisten = \"0.0.0.0:8080\"\nredirect = 80\n";
+
+        // When
+        let err = parse_config::<Config>("config.toml", contents).unwrap_err();
+
+        // Then
+        let ConfigError::Invalid(errors) = err else {
+            panic!("expected an invalid configuration");
+        };
+        assert!(errors[0].starts_with("error: invalid type: integer `80`, expected a string"));
+        assert!(errors[0].contains("--> config.toml:3:12"));
+    }
+
+    #[test]
+    fn should_point_at_the_line_of_a_field_before_the_type() {
+        // Given
+        let contents =
+            "[[servers]]\nname = \"x\"\nlisten = 80\ntype = \"tcp\"\nredirect = \"127.0.0.1:80\"\n";
+
+        // When
+        let err = parse_config::<Config>("config.toml", contents).unwrap_err();
+
+        // Then
+        let ConfigError::Invalid(errors) = err else {
+            panic!("expected an invalid configuration");
+        };
+        assert!(errors[0].contains("--> config.toml:3:10"));
+    }
+
+    #[test]
+    fn should_point_at_a_type_which_is_not_a_string() {
+        // Given
+        let contents = "[[servers]]\nlisten = \"0.0.0.0:8080\"\ntype = 5\n";
+
+        // When
+        let err = parse_config::<Config>("config.toml", contents).unwrap_err();
+
+        // Then
+        let ConfigError::Invalid(errors) = err else {
+            panic!("expected an invalid configuration");
+        };
+        assert!(errors[0].starts_with("error: invalid type: integer `5`, expected a string"));
+        assert!(errors[0].contains("--> config.toml:3:8"));
+    }
+
+    #[test]
+    fn should_parse_explicit_and_inferred_server_types() {
+        // Given
+        let contents = r#"
+            version = 1
+
+            [[servers]]
+            name = "lobby"
+            type = "minecraft"
+            listen = "0.0.0.0:25565"
+            hosts = [{ hostname = "localhost", target = "127.0.0.1:25566" }]
+
+            [[servers]]
+            type = "tcp"
+            listen = "0.0.0.0:8080"
+            redirect = "127.0.0.1:80"
+
+            [[servers]]
+            listen = "0.0.0.0:8081"
+            redirect = "127.0.0.1:81"
+
+            [[servers]]
+            type = "udp"
+            listen = "0.0.0.0:19132"
+            redirect = "127.0.0.1:19133"
+        "#;
+
+        // When
+        let config = parse_config::<Config>("config.toml", contents).unwrap();
+
+        // Then
+        let Servers::Minecraft { listener, .. } = &config.servers[0] else {
+            panic!("expected a Minecraft server");
+        };
+        assert_eq!(listener.name.as_deref(), Some("lobby"));
+        assert!(matches!(config.servers[1], Servers::Tcp { .. }));
+        assert!(matches!(config.servers[2], Servers::Tcp { .. }));
+        assert!(matches!(config.servers[3], Servers::Udp { .. }));
+    }
+
+    #[test]
+    fn should_name_the_missing_field_of_a_server_type() {
+        // Given
+        let contents = "[[servers]]\ntype = \"tls\"\nlisten = \"0.0.0.0:443\"\n";
+
+        // When
+        let err = parse_config::<Config>("config.toml", contents).unwrap_err();
+
+        // Then
+        let ConfigError::Invalid(errors) = err else {
+            panic!("expected an invalid configuration");
+        };
+        assert!(errors[0].starts_with("error: missing field `hosts`"));
+        assert!(errors[0].contains("--> config.toml:1:1"));
+    }
+
+    #[test]
+    fn should_keep_the_error_of_an_unreadable_file() {
+        // When
+        let err = read_config("/nonexistent/config.toml").unwrap_err();
+
+        // Then
+        let ConfigError::CannotRead { source, .. } = &err else {
+            panic!("expected an unreadable file");
+        };
+        assert_eq!(source.kind(), io::ErrorKind::NotFound);
+        assert!(err
+            .to_string()
+            .starts_with("cannot read /nonexistent/config.toml: "));
     }
 }
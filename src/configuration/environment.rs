@@ -0,0 +1,173 @@
+use std::env;
+use std::ops::Range;
+
+use toml_edit::{ImDocument, Item, Table, Value};
+
+use crate::configuration::validation::Diagnostic;
+
+/// Replaces `${NAME}` and `${NAME:-default}` in the string values of a configuration file with
+/// environment variables, `$${` being a literal `${`. The values are rewritten in place, so the
+/// problems of the file point at the same lines.
+pub(crate) fn substitute_env(contents: &str) -> Result<String, Diagnostic> {
+    substitute_variables(contents, |name| env::var(name).ok())
+}
+
+fn substitute_variables(
+    contents: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, Diagnostic> {
+    if !contents.contains("${") {
+        return Ok(contents.to_string());
+    }
+    // Syntax errors are reported when the file is parsed
+    let Ok(document) = ImDocument::parse(contents) else {
+        return Ok(contents.to_string());
+    };
+
+    let mut strings = Vec::new();
+    table_strings(&document, &mut strings);
+
+    let mut substituted = contents.to_string();
+    strings.sort_by_key(|(_, span)| span.start);
+    // From the end, for the spans before to stay valid
+    for (value, span) in strings.into_iter().rev() {
+        if !value.contains("${") {
+            continue;
+        }
+        let replaced = substitute(value, &lookup)
+            .map_err(|message| Diagnostic::error(message, Some(span.clone())))?;
+        substituted.replace_range(span, &Value::from(replaced).to_string());
+    }
+    Ok(substituted)
+}
+
+fn substitute(value: &str, lookup: &impl Fn(&str) -> Option<String>) -> Result<String, String> {
+    let mut substituted = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        substituted.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix("$${") {
+            substituted.push_str("${");
+            rest = after;
+            continue;
+        }
+        let Some(after) = rest.strip_prefix("${") else {
+            substituted.push('$');
+            rest = &rest[1..];
+            continue;
+        };
+
+        let end = after
+            .find('}')
+            .ok_or_else(|| format!("unclosed ${{ in {value}"))?;
+        let (name, default) = match after[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&after[..end], None),
+        };
+        if name.is_empty() {
+            return Err(format!("expected a variable name after ${{ in {value}"));
+        }
+        // As in shells, the default also replaces an empty variable
+        match (lookup(name), default) {
+            (Some(variable), _) if !variable.is_empty() => substituted.push_str(&variable),
+            (_, Some(default)) => substituted.push_str(default),
+            (Some(variable), None) => substituted.push_str(&variable),
+            (None, None) => return Err(format!("environment variable {name} is not set")),
+        }
+        rest = &after[end + 1..];
+    }
+    substituted.push_str(rest);
+    Ok(substituted)
+}
+
+fn table_strings<'a>(table: &'a Table, strings: &mut Vec<(&'a str, Range<usize>)>) {
+    for (_, item) in table.iter() {
+        match item {
+            Item::Value(value) => value_strings(value, strings),
+            Item::Table(table) => table_strings(table, strings),
+            Item::ArrayOfTables(tables) => {
+                for table in tables.iter() {
+                    table_strings(table, strings);
+                }
+            }
+            Item::None => {}
+        }
+    }
+}
+
+fn value_strings<'a>(value: &'a Value, strings: &mut Vec<(&'a str, Range<usize>)>) {
+    match value {
+        Value::String(string) => {
+            if let Some(span) = value.span() {
+                strings.push((string.value(), span));
+            }
+        }
+        Value::Array(array) => {
+            for value in array.iter() {
+                value_strings(value, strings);
+            }
+        }
+        Value::InlineTable(table) => {
+            for (_, value) in table.iter() {
+                value_strings(value, strings);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "TARGET" => Some("10.0.0.1:25565".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn should_substitute_variables_in_string_values() {
+        // Given
+        let contents = r#"# ${TARGET} in a comment
+[[servers]]
+listen = "${PORT:-0.0.0.0:25565}"
+hosts = [
+    { hostname = "${EMPTY:-localhost}", target = "${TARGET}" },
+    { hostname = "literal", target = "$${TARGET}$5" },
+]
+"#;
+
+        // When
+        let substituted = substitute_variables(contents, lookup).unwrap();
+
+        // Then
+        assert_eq!(
+            substituted,
+            r#"# ${TARGET} in a comment
+[[servers]]
+listen = "0.0.0.0:25565"
+hosts = [
+    { hostname = "localhost", target = "10.0.0.1:25565" },
+    { hostname = "literal", target = "${TARGET}$5" },
+]
+"#
+        );
+    }
+
+    #[test]
+    fn should_report_unset_variables() {
+        // Given
+        let contents = "[[servers]]\nlisten = \"0.0.0.0:25565\"\nredirect = \"${BACKEND}\"\n";
+
+        // When
+        let err = substitute_variables(contents, lookup).unwrap_err();
+
+        // Then
+        assert_eq!(err.message, "environment variable BACKEND is not set");
+        assert_eq!(err.span, Some(48..60));
+    }
+}
@@ -0,0 +1,343 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::configuration::server_type::TypedServer;
+use crate::configuration::validation::{normalize, Diagnostic};
+use crate::configuration::{parse_config, Config, ConfigError, ConfigSource, Servers};
+
+/// Server blocks and hosts added by an included file. The hosts name the server block routing
+/// them, which can come from any file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IncludedFile {
+    #[serde(default)]
+    servers: Vec<Spanned<TypedServer>>,
+    #[serde(default)]
+    hosts: Vec<Spanned<toml::Table>>,
+}
+
+/// Merges the files included by the configuration at `path` into it, adding their sources.
+/// Server blocks with the name or a listen address of another block, and hosts routed twice by a
+/// server block, are reported as conflicts.
+pub(super) fn include_files(
+    config: &mut Config,
+    path: &str,
+    sources: &mut Vec<ConfigSource>,
+) -> Result<(), ConfigError> {
+    // The parent of a bare file name is empty
+    let directory = match Path::new(path).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let mut files = Vec::new();
+    for pattern in &config.include {
+        files.extend(expand(&directory.join(pattern))?);
+    }
+
+    let mut errors = Vec::new();
+    let mut hosts = Vec::new();
+    for file in files {
+        let source = ConfigSource::read(&file.to_string_lossy())?;
+        let included = parse_config::<IncludedFile>(&source.path, &source.contents)?;
+        for server in included.servers {
+            let span = server.span();
+            let server = server.into_inner().0;
+            match conflict(config, &server) {
+                Some(message) => errors.push(
+                    Diagnostic::error(message, Some(span)).render(&source.path, &source.contents),
+                ),
+                None => config.servers.push(server),
+            }
+        }
+        hosts.push((sources.len(), included.hosts));
+        sources.push(source);
+    }
+
+    // The hosts are added once every file is read, as they can go to a block of a later file
+    for (index, entries) in hosts {
+        let source = &sources[index];
+        for entry in entries {
+            let span = entry.span();
+            if let Err(message) = add_host(config, entry.into_inner()) {
+                errors.push(
+                    Diagnostic::error(message, Some(span)).render(&source.path, &source.contents),
+                );
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(ConfigError::Invalid(errors));
+    }
+    Ok(())
+}
+
+fn conflict(config: &Config, server: &Servers) -> Option<String> {
+    let listener = server.listener();
+    if let Some(name) = &listener.name {
+        if config
+            .servers
+            .iter()
+            .any(|existing| existing.listener().name.as_ref() == Some(name))
+        {
+            return Some(format!("duplicate server block name {name}"));
+        }
+    }
+
+    let listened = config
+        .servers
+        .iter()
+        .filter(|existing| existing.protocol() == server.protocol())
+        .flat_map(|existing| existing.listener().listen.0.iter())
+        .map(|address| normalize(address))
+        .collect::<HashSet<_>>();
+    listener
+        .listen
+        .0
+        .iter()
+        .find(|address| listened.contains(&normalize(address)))
+        .map(|address| format!("duplicate listen address {address}"))
+}
+
+fn add_host(config: &mut Config, mut host: toml::Table) -> Result<(), String> {
+    let Some(toml::Value::String(name)) = host.remove("server") else {
+        return Err("expected the name of the server block routing the host in `server`".into());
+    };
+    let server = config
+        .servers
+        .iter_mut()
+        .find(|server| server.listener().name.as_ref() == Some(&name))
+        .ok_or_else(|| format!("no server block is named {name}"))?;
+    server
+        .add_host(host)
+        .map_err(|message| format!("cannot add the host to the server block {name}: {message}"))
+}
+
+/// Files matching a path of which the file name can have `*` and `?` wildcards, in the order of
+/// their names. A path without wildcards is returned as is, even when the file does not exist.
+fn expand(pattern: &Path) -> Result<Vec<PathBuf>, ConfigError> {
+    let (Some(directory), Some(name)) = (
+        pattern.parent(),
+        pattern.file_name().and_then(|name| name.to_str()),
+    ) else {
+        return Ok(vec![pattern.to_path_buf()]);
+    };
+    if !name.contains(['*', '?']) {
+        return Ok(vec![pattern.to_path_buf()]);
+    }
+
+    let entries = fs::read_dir(directory).map_err(|source| ConfigError::CannotRead {
+        path: directory.to_string_lossy().into_owned(),
+        source,
+    })?;
+    let mut files = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|file| file.to_str())
+                .is_some_and(|file| matches_wildcards(name, file))
+                && path.is_file()
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    Ok(files)
+}
+
+fn matches_wildcards(pattern: &str, name: &str) -> bool {
+    let mut pattern_chars = pattern.chars();
+    match pattern_chars.next() {
+        None => name.is_empty(),
+        Some('*') => {
+            let rest = pattern_chars.as_str();
+            name.char_indices()
+                .map(|(index, _)| index)
+                .chain([name.len()])
+                .any(|index| matches_wildcards(rest, &name[index..]))
+        }
+        Some('?') => {
+            let mut name_chars = name.chars();
+            name_chars.next().is_some()
+                && matches_wildcards(pattern_chars.as_str(), name_chars.as_str())
+        }
+        Some(char) => name
+            .strip_prefix(char)
+            .is_some_and(|rest| matches_wildcards(pattern_chars.as_str(), rest)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::load_config;
+    use std::env;
+    use std::process;
+
+    fn config_directory(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory = env::temp_dir().join(format!("proxy-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("conf.d")).unwrap();
+        for (path, contents) in files {
+            fs::write(directory.join(path), contents).unwrap();
+        }
+        directory
+    }
+
+    #[test]
+    fn should_match_file_names_with_wildcards() {
+        assert!(matches_wildcards("*.toml", "team-a.toml"));
+        assert!(matches_wildcards("team-?.toml", "team-a.toml"));
+        assert!(matches_wildcards("*", ""));
+        assert!(!matches_wildcards("*.toml", "team-a.toml.bak"));
+        assert!(!matches_wildcards("team-?.toml", "team-ab.toml"));
+    }
+
+    #[test]
+    fn should_merge_server_blocks_and_hosts_of_included_files() {
+        // Given
+        let directory = config_directory(
+            "include",
+            &[
+                (
+                    "config.toml",
+                    r#"
+include = ["conf.d/*.toml"]
+
+[[servers]]
+name = "lobby"
+listen = "0.0.0.0:25565"
+hosts = [{ hostname = "localhost", target = "127.0.0.1:25566" }]
+"#,
+                ),
+                (
+                    "conf.d/a.toml",
+                    r#"
+[[hosts]]
+server = "web"
+hostname = "example.com"
+target = "127.0.0.1:8081"
+"#,
+                ),
+                (
+                    "conf.d/b.toml",
+                    r#"
+[[servers]]
+name = "web"
+type = "http"
+listen = "0.0.0.0:8080"
+hosts = []
+
+[[hosts]]
+server = "lobby"
+hostname = "play.example.com"
+target = "127.0.0.1:25567"
+"#,
+                ),
+            ],
+        );
+
+        // When
+        let (config, sources) =
+            load_config(&directory.join("config.toml").to_string_lossy()).unwrap();
+
+        // Then
+        assert_eq!(sources.len(), 3);
+        let Servers::Minecraft { hosts, .. } = &config.servers[0] else {
+            panic!("expected a Minecraft server");
+        };
+        let hostnames = hosts.iter().map(|host| host.hostname.as_str());
+        assert_eq!(
+            hostnames.collect::<Vec<_>>(),
+            vec!["localhost", "play.example.com"]
+        );
+        let Servers::Http { hosts, .. } = &config.servers[1] else {
+            panic!("expected an HTTP server");
+        };
+        assert_eq!(hosts[0].target, "127.0.0.1:8081");
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn should_include_files_next_to_a_bare_file_name() {
+        // Given
+        let directory = config_directory(
+            "relative",
+            &[
+                (
+                    "config.toml",
+                    "include = [\"routes-*.toml\"]\nservers = []\n",
+                ),
+                (
+                    "routes-a.toml",
+                    "[[servers]]\nlisten = \"0.0.0.0:8080\"\nredirect = \"127.0.0.1:80\"\n",
+                ),
+            ],
+        );
+        let current_dir = env::current_dir().unwrap();
+        env::set_current_dir(&directory).unwrap();
+
+        // When
+        let loaded = load_config("config.toml");
+
+        // Then
+        env::set_current_dir(current_dir).unwrap();
+        let (config, sources) = loaded.unwrap();
+        assert_eq!(sources.len(), 2);
+        assert_eq!(config.servers.len(), 1);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn should_report_conflicts_of_included_files() {
+        // Given
+        let directory = config_directory(
+            "conflict",
+            &[
+                (
+                    "config.toml",
+                    r#"
+include = ["conf.d/*.toml"]
+
+[[servers]]
+name = "lobby"
+listen = "0.0.0.0:25565"
+hosts = [{ hostname = "localhost", target = "127.0.0.1:25566" }]
+"#,
+                ),
+                (
+                    "conf.d/a.toml",
+                    r#"
+[[servers]]
+listen = "0.0.0.0:25565"
+redirect = "127.0.0.1:80"
+
+[[hosts]]
+server = "lobby"
+hostname = "LocalHost"
+target = "127.0.0.1:25567"
+"#,
+                ),
+            ],
+        );
+
+        // When
+        let err = load_config(&directory.join("config.toml").to_string_lossy()).unwrap_err();
+
+        // Then
+        let ConfigError::Invalid(errors) = err else {
+            panic!("expected an invalid configuration");
+        };
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("error: duplicate listen address 0.0.0.0:25565"));
+        assert!(errors[0].contains("conf.d/a.toml:2:1"));
+        assert!(errors[1].starts_with(
+            "error: cannot add the host to the server block lobby: duplicate hostname localhost"
+        ));
+        assert!(errors[1].contains("conf.d/a.toml:6:1"));
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
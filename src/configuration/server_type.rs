@@ -0,0 +1,282 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+
+use serde::de::value::{MapAccessDeserializer, MapDeserializer, StrDeserializer};
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, VariantAccess, Visitor,
+};
+use serde::{forward_to_deserialize_any, Deserialize, Deserializer};
+
+use crate::configuration::validation::server_kinds;
+use crate::configuration::{ListenerConfig, OutboundConfig, Servers};
+
+thread_local! {
+    /// Kinds of the server blocks of the file being parsed, not read yet.
+    static KINDS: RefCell<VecDeque<Option<String>>> = const { RefCell::new(VecDeque::new()) };
+}
+
+/// Runs `parse` on the contents of a configuration file, its server blocks being read as the
+/// kinds found in the contents beforehand, so `type` can be anywhere in a block.
+pub(crate) fn with_server_kinds<R>(contents: &str, parse: impl FnOnce() -> R) -> R {
+    KINDS.set(server_kinds(contents).into());
+    let result = parse();
+    KINDS.take();
+    result
+}
+
+/// Server block of which the fields are handed to the variant of its kind as they are read, for
+/// the errors of their values to keep their spans.
+pub(crate) struct TypedServer(pub(crate) Servers);
+
+impl<'de> Deserialize<'de> for TypedServer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(TypedServerVisitor)
+    }
+}
+
+pub(crate) fn deserialize_servers<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Servers>, D::Error> {
+    let servers = Vec::<TypedServer>::deserialize(deserializer)?;
+    Ok(servers.into_iter().map(|server| server.0).collect())
+}
+
+struct TypedServerVisitor;
+
+impl<'de> Visitor<'de> for TypedServerVisitor {
+    type Value = TypedServer;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a server block")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<TypedServer, A::Error> {
+        let Some(kind) = KINDS.with_borrow_mut(VecDeque::pop_front) else {
+            return Err(de::Error::custom(
+                "server blocks are only read from configuration files",
+            ));
+        };
+        let Some(kind) = kind else {
+            // Reads `type` as a string, which it is not, for its error
+            while let Some(key) = map.next_key::<String>()? {
+                match key.as_str() {
+                    "type" => drop(map.next_value::<String>()?),
+                    _ => drop(map.next_value::<de::IgnoredAny>()?),
+                }
+            }
+            return Err(de::Error::missing_field("type"));
+        };
+        let fields = ServerFields {
+            kind,
+            key: None,
+            map,
+        };
+        Servers::deserialize(TaggedServer(fields)).map(TypedServer)
+    }
+}
+
+/// Server block read as an enum by [`Servers`], of which the variant is named by its kind.
+struct TaggedServer<A>(ServerFields<A>);
+
+impl<'de, A: MapAccess<'de>> Deserializer<'de> for TaggedServer<A> {
+    type Error = A::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, A::Error> {
+        visitor.visit_enum(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map struct enum identifier
+        ignored_any
+    }
+}
+
+impl<'de, A: MapAccess<'de>> de::EnumAccess<'de> for TaggedServer<A> {
+    type Error = A::Error;
+    type Variant = ServerFields<A>;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(
+        self,
+        seed: S,
+    ) -> Result<(S::Value, ServerFields<A>), A::Error> {
+        let kind: StrDeserializer<A::Error> = self.0.kind.as_str().into_deserializer();
+        Ok((seed.deserialize(kind)?, self.0))
+    }
+}
+
+/// Fields of a server block but `type`, which names the variant.
+struct ServerFields<A> {
+    kind: String,
+    /// Key of the value read next.
+    key: Option<String>,
+    map: A,
+}
+
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for ServerFields<A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, A::Error> {
+        loop {
+            match self.map.next_key::<String>()? {
+                Some(key) if key == "type" => drop(self.map.next_value::<de::IgnoredAny>()?),
+                Some(key) => {
+                    self.key = Some(key.clone());
+                    return seed.deserialize(key.into_deserializer()).map(Some);
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, A::Error> {
+        self.map.next_value_seed(CheckedValue {
+            kind: &self.kind,
+            key: self.key.take().unwrap_or_default(),
+            seed,
+        })
+    }
+}
+
+impl<'de, A: MapAccess<'de>> VariantAccess<'de> for ServerFields<A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), A::Error> {
+        Err(de::Error::custom("expected the fields of a server block"))
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, A::Error> {
+        seed.deserialize(MapAccessDeserializer::new(self))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _: usize, _: V) -> Result<V::Value, A::Error> {
+        Err(de::Error::custom("expected the fields of a server block"))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, A::Error> {
+        visitor.visit_map(self)
+    }
+}
+
+/// Value of a field, failing while the parser still knows where it is written. The fields of the
+/// structs flattened in a variant are only read once the whole block is, so they are checked
+/// against these structs first.
+struct CheckedValue<'a, S> {
+    kind: &'a str,
+    key: String,
+    seed: S,
+}
+
+impl<'de, S: DeserializeSeed<'de>> DeserializeSeed<'de> for CheckedValue<'_, S> {
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<S::Value, D::Error> {
+        let value = toml::Value::deserialize(deserializer)?;
+        // The structs flattened in the variants of `Servers`
+        probe::<ListenerConfig>(&self.key, &value).map_err(de::Error::custom)?;
+        if self.kind == "tcp" {
+            probe::<OutboundConfig>(&self.key, &value).map_err(de::Error::custom)?;
+        }
+        self.seed
+            .deserialize(value)
+            .map_err(|err| de::Error::custom(err.message()))
+    }
+}
+
+/// Reads a struct from a single field, succeeding when the field is not one of the struct.
+fn probe<T: DeserializeOwned>(key: &str, value: &toml::Value) -> Result<(), String> {
+    let field = [(key.to_string(), ProbeValue(value.clone()))];
+    match T::deserialize(MapDeserializer::new(field.into_iter())) {
+        Ok(_) | Err(ProbeError::Missing) => Ok(()),
+        Err(ProbeError::Invalid(message)) => Err(message),
+    }
+}
+
+/// Error of a probe, the other fields of the struct being missing from it.
+#[derive(Debug)]
+enum ProbeError {
+    Missing,
+    Invalid(String),
+}
+
+impl de::Error for ProbeError {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        ProbeError::Invalid(message.to_string())
+    }
+
+    fn missing_field(_: &'static str) -> Self {
+        ProbeError::Missing
+    }
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProbeError::Missing => write!(f, "missing field"),
+            ProbeError::Invalid(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for ProbeError {}
+
+/// Value of a probed field, of which the errors are invalid values.
+struct ProbeValue(toml::Value);
+
+impl<'de> IntoDeserializer<'de, ProbeError> for ProbeValue {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> Deserializer<'de> for ProbeValue {
+    type Error = ProbeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ProbeError> {
+        self.0.deserialize_any(visitor).map_err(invalid_value)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ProbeError> {
+        self.0.deserialize_option(visitor).map_err(invalid_value)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, ProbeError> {
+        self.0
+            .deserialize_newtype_struct(name, visitor)
+            .map_err(invalid_value)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ProbeError> {
+        self.0
+            .deserialize_enum(name, variants, visitor)
+            .map_err(invalid_value)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+fn invalid_value(err: toml::de::Error) -> ProbeError {
+    ProbeError::Invalid(err.message().to_string())
+}
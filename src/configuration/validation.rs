@@ -0,0 +1,614 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::net::SocketAddr;
+use std::ops::Range;
+
+use toml_edit::{ImDocument, Item, TableLike};
+
+use crate::backends::resolver::srv_name;
+use crate::backends::stream::unix_path;
+use crate::configuration::{default_dns_max_ttl_seconds, route_name, CONFIG_VERSION};
+
+/// Whether a problem stops the proxy from starting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Problem of a configuration file, located by the byte range of the value it is about.
+#[derive(Debug, Clone)]
+pub(crate) struct Diagnostic {
+    pub(crate) severity: Severity,
+    pub(crate) message: String,
+    pub(crate) span: Option<Range<usize>>,
+}
+
+impl Diagnostic {
+    pub(crate) fn error(message: impl Into<String>, span: Option<Range<usize>>) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+            span,
+        }
+    }
+
+    pub(crate) fn warning(message: impl Into<String>, span: Option<Range<usize>>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message: message.into(),
+            span,
+        }
+    }
+
+    /// Error of the toml parser, with a hint instead of the message of an untagged enum.
+    pub(crate) fn from_toml(err: &toml::de::Error) -> Self {
+        let message = match err.message().trim_end() {
+            "data did not match any variant of untagged enum OneOrMany" => {
+                "expected an address or a list of addresses"
+            }
+            message => message,
+        };
+        Self::error(message, err.span())
+    }
+
+    /// Renders the message along with the line it points at, such as:
+    ///
+    /// ```text
+    /// error: duplicate listen address 0.0.0.0:25565
+    ///  --> config.toml:7:10
+    ///   |
+    /// 7 | listen = "0.0.0.0:25565"
+    ///   |          ^^^^^^^^^^^^^^^
+    /// ```
+    pub(crate) fn render(&self, path: &str, contents: &str) -> String {
+        let mut rendered = format!("{}: {}", self.severity, self.message);
+        let Some(span) = &self.span else {
+            rendered.push_str(&format!("\n --> {path}"));
+            return rendered;
+        };
+
+        let start = span.start.min(contents.len());
+        let line_start = contents[..start].rfind('\n').map_or(0, |index| index + 1);
+        let line_end = contents[start..]
+            .find('\n')
+            .map_or(contents.len(), |index| start + index);
+        let line = contents[line_start..line_end].trim_end_matches('\r');
+        let number = contents[..start].matches('\n').count() + 1;
+        let column = contents[line_start..start].chars().count();
+        let width = contents[start..span.end.clamp(start, line_end)]
+            .chars()
+            .count()
+            .max(1);
+
+        let gutter = " ".repeat(number.to_string().len());
+        rendered.push_str(&format!(
+            "\n{gutter}--> {path}:{number}:{}\n{gutter} |\n{number} | {line}\n{gutter} | {}{}",
+            column + 1,
+            " ".repeat(column),
+            "^".repeat(width)
+        ));
+        rendered
+    }
+}
+
+/// Transport of a listen address or a target, the same port can be used by both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Protocol {
+    Tcp,
+    Udp,
+}
+
+/// Target of a route, along with where it is written.
+#[derive(Debug, Clone)]
+pub(crate) struct Target {
+    pub(crate) address: String,
+    pub(crate) protocol: Protocol,
+    pub(crate) span: Option<Range<usize>>,
+}
+
+/// Checks a configuration which parsed: its version, the DNS TTL bounds, the format of the
+/// addresses, listen addresses and names used by several server blocks, and hostnames routed twice
+/// by a server block.
+pub(crate) fn validate(contents: &str) -> Vec<Diagnostic> {
+    let Ok(document) = ImDocument::parse(contents) else {
+        return Vec::new();
+    };
+
+    let mut diagnostics = Vec::new();
+    if let Some(version) = document.get("version") {
+        match version.as_integer() {
+            Some(1..=CONFIG_VERSION) => {}
+            Some(number) => diagnostics.push(Diagnostic::error(
+                format!(
+                    "unsupported configuration version {number}, this proxy reads versions 1 to \
+                     {CONFIG_VERSION}"
+                ),
+                version.span(),
+            )),
+            None => diagnostics.push(Diagnostic::error(
+                "expected an integer version",
+                version.span(),
+            )),
+        }
+    }
+
+    if let Some(dns) = document.get("dns").and_then(Item::as_table_like) {
+        let seconds = |key| dns.get(key).and_then(Item::as_integer);
+        let max_ttl = seconds("max_ttl_seconds").unwrap_or(default_dns_max_ttl_seconds() as i64);
+        if let Some(min_ttl) = seconds("min_ttl_seconds").filter(|min_ttl| *min_ttl > max_ttl) {
+            diagnostics.push(Diagnostic::error(
+                format!("min_ttl_seconds {min_ttl} is above max_ttl_seconds {max_ttl}"),
+                dns.get("min_ttl_seconds").and_then(Item::span),
+            ));
+        }
+    }
+
+    let mut named = HashMap::new();
+    let mut listened = HashMap::new();
+    for server in server_blocks(&document) {
+        if let Some((name, span)) = strings(server.table.get("name")).into_iter().next() {
+            match named.get(name) {
+                Some(first) => diagnostics.push(Diagnostic::error(
+                    format!(
+                        "duplicate server block name {name}, already used{}",
+                        at_line(contents, first)
+                    ),
+                    span,
+                )),
+                None => {
+                    named.insert(name, span);
+                }
+            }
+        }
+
+        let protocol = server.protocol();
+        let listen = server.table.get("listen");
+        let query = server
+            .table
+            .get("query")
+            .and_then(Item::as_table_like)
+            .and_then(|query| query.get("listen"));
+        let addresses = strings(listen)
+            .into_iter()
+            .map(|address| (address, protocol))
+            .chain(
+                strings(query)
+                    .into_iter()
+                    .map(|query| (query, Protocol::Udp)),
+            );
+
+        for ((address, span), protocol) in addresses {
+            if let Err(problem) = check_listen_address(address, protocol) {
+                diagnostics.push(Diagnostic::error(
+                    format!("invalid listen address {address}, {problem}"),
+                    span,
+                ));
+                continue;
+            }
+            let key = (protocol, normalize(address));
+            match listened.get(&key) {
+                Some(first) => diagnostics.push(Diagnostic::error(
+                    format!(
+                        "duplicate listen address {address}, already listened on{}",
+                        at_line(contents, first)
+                    ),
+                    span,
+                )),
+                None => {
+                    listened.insert(key, span);
+                }
+            }
+        }
+
+        for target in server.targets() {
+            if let Err(problem) = check_target(&target.address, target.protocol) {
+                diagnostics.push(Diagnostic::error(
+                    format!("invalid target {}, {problem}", target.address),
+                    target.span,
+                ));
+            }
+        }
+
+        let mut routed = HashMap::new();
+        for (route, span) in server.routes() {
+            match routed.get(&route) {
+                Some(first) => diagnostics.push(Diagnostic::error(
+                    format!(
+                        "duplicate hostname {route}, already routed{}",
+                        at_line(contents, first)
+                    ),
+                    span,
+                )),
+                None => {
+                    routed.insert(route, span);
+                }
+            }
+        }
+    }
+
+    // Hosts of an included file, routed by a server block of any file
+    for host in tables(document.get("hosts")) {
+        for (address, span) in strings(host.get("target")) {
+            if let Err(problem) = check_target(address, Protocol::Tcp) {
+                diagnostics.push(Diagnostic::error(
+                    format!("invalid target {address}, {problem}"),
+                    span,
+                ));
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Targets of every route of a configuration file which parsed, the hosts of an included file
+/// having the protocol of the server block named by `server`.
+pub(crate) fn targets(contents: &str, protocols: &HashMap<String, Protocol>) -> Vec<Target> {
+    let Ok(document) = ImDocument::parse(contents) else {
+        return Vec::new();
+    };
+    let included = tables(document.get("hosts")).into_iter().flat_map(|host| {
+        let server = host
+            .get("server")
+            .and_then(Item::as_str)
+            .unwrap_or_default();
+        let protocol = protocols.get(server).copied().unwrap_or(Protocol::Tcp);
+        strings(host.get("target"))
+            .into_iter()
+            .map(move |(address, span)| Target {
+                address: address.to_string(),
+                protocol,
+                span,
+            })
+    });
+    server_blocks(&document)
+        .iter()
+        .flat_map(ServerBlock::targets)
+        .chain(included)
+        .collect()
+}
+
+/// Server block of the document, the kind of server is given by `type` or inferred from `hosts`.
+struct ServerBlock<'a> {
+    kind: &'a str,
+    table: &'a dyn TableLike,
+}
+
+impl ServerBlock<'_> {
+    fn protocol(&self) -> Protocol {
+        match self.kind {
+            "udp" | "bedrock" => Protocol::Udp,
+            _ => Protocol::Tcp,
+        }
+    }
+
+    fn hosts(&self) -> Vec<&dyn TableLike> {
+        tables(self.table.get("hosts"))
+    }
+
+    fn targets(&self) -> Vec<Target> {
+        let redirect = strings(self.table.get("redirect"));
+        let hosts = self.hosts();
+        let routes = hosts.iter().flat_map(|host| strings(host.get("target")));
+        redirect
+            .into_iter()
+            .chain(routes)
+            .map(|(address, span)| Target {
+                address: address.to_string(),
+                protocol: self.protocol(),
+                span,
+            })
+            .collect()
+    }
+
+    /// What tells the routes of the server block apart, with where it is written.
+    fn routes(&self) -> Vec<(String, Option<Range<usize>>)> {
+        let (name, discriminant) = match self.kind {
+            "tls" => ("hostname", Some("alpn")),
+            "http" => ("hostname", Some("path_prefix")),
+            "bedrock" => ("server_address", None),
+            _ => ("hostname", None),
+        };
+        self.hosts()
+            .into_iter()
+            .filter_map(|host| {
+                let (hostname, span) = strings(host.get(name)).into_iter().next()?;
+                let discriminant = discriminant.and_then(|key| host.get(key)?.as_str());
+                Some((route_name(hostname, discriminant), span))
+            })
+            .collect()
+    }
+}
+
+fn server_blocks<S>(document: &ImDocument<S>) -> Vec<ServerBlock<'_>> {
+    tables(document.get("servers"))
+        .into_iter()
+        .map(|table| ServerBlock {
+            kind: server_kind(table).unwrap_or_default(),
+            table,
+        })
+        .collect()
+}
+
+/// Kinds of the server blocks of a configuration file, in order.
+pub(crate) fn server_kinds(contents: &str) -> Vec<Option<String>> {
+    let Ok(document) = ImDocument::parse(contents) else {
+        return Vec::new();
+    };
+    tables(document.get("servers"))
+        .into_iter()
+        .map(|table| server_kind(table).map(str::to_string))
+        .collect()
+}
+
+/// Kind of server given by `type`, or for blocks written before it existed, `minecraft` with
+/// `hosts` and `tcp` otherwise. `None` when `type` is not a string.
+fn server_kind(table: &dyn TableLike) -> Option<&str> {
+    match table.get("type") {
+        Some(kind) => kind.as_str(),
+        None if table.contains_key("hosts") => Some("minecraft"),
+        None => Some("tcp"),
+    }
+}
+
+/// Tables of an array of tables, or of an array of inline tables.
+fn tables(item: Option<&Item>) -> Vec<&dyn TableLike> {
+    match item {
+        Some(Item::ArrayOfTables(tables)) => {
+            tables.iter().map(|table| table as &dyn TableLike).collect()
+        }
+        Some(Item::Value(tables)) => tables
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|table| table.as_inline_table())
+            .map(|table| table as &dyn TableLike)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Strings of a value which is a string or an array of strings.
+fn strings(item: Option<&Item>) -> Vec<(&str, Option<Range<usize>>)> {
+    let Some(Item::Value(value)) = item else {
+        return Vec::new();
+    };
+    match value.as_array() {
+        Some(values) => values
+            .iter()
+            .filter_map(|value| Some((value.as_str()?, value.span())))
+            .collect(),
+        None => value
+            .as_str()
+            .map(|string| (string, value.span()))
+            .into_iter()
+            .collect(),
+    }
+}
+
+fn check_listen_address(address: &str, protocol: Protocol) -> Result<(), &'static str> {
+    match unix_path(address) {
+        Some(_) if protocol == Protocol::Udp => Err("UDP servers cannot listen on a Unix socket"),
+        Some("") => Err("expected a path after unix:"),
+        Some(_) => Ok(()),
+        None => check_host_and_port(address),
+    }
+}
+
+fn check_target(address: &str, protocol: Protocol) -> Result<(), &'static str> {
+    match (unix_path(address), srv_name(address)) {
+        (Some(_), _) if protocol == Protocol::Udp => Err("UDP targets cannot be Unix sockets"),
+        (Some(""), _) => Err("expected a path after unix:"),
+        (_, Some("")) => Err("expected a name after srv:"),
+        (Some(_), _) | (_, Some(_)) => Ok(()),
+        _ => check_host_and_port(address),
+    }
+}
+
+fn check_host_and_port(address: &str) -> Result<(), &'static str> {
+    if address.parse::<SocketAddr>().is_ok() {
+        return Ok(());
+    }
+    let Some((host, port)) = address.rsplit_once(':') else {
+        return Err("expected host:port");
+    };
+    if host.is_empty() || host.contains(':') {
+        return Err("expected host:port, with IPv6 addresses in brackets");
+    }
+    port.parse::<u16>()
+        .map(|_| ())
+        .map_err(|_| "expected a port between 0 and 65535")
+}
+
+/// Compares IP addresses regardless of how they are written, and hostnames regardless of case.
+pub(crate) fn normalize(address: &str) -> String {
+    match address.parse::<SocketAddr>() {
+        Ok(address) => address.to_string(),
+        Err(_) => address.to_lowercase(),
+    }
+}
+
+fn at_line(contents: &str, span: &Option<Range<usize>>) -> String {
+    match span {
+        Some(span) => {
+            let start = span.start.min(contents.len());
+            format!(" at line {}", contents[..start].matches('\n').count() + 1)
+        }
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(contents: &str) -> Vec<String> {
+        validate(contents)
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    #[test]
+    fn should_report_duplicate_listen_addresses_per_protocol() {
+        // Given
+        let contents = r#"
+[[servers]]
+listen = ["0.0.0.0:25565", "[::]:25565"]
+hosts = [{ hostname = "localhost", target = "127.0.0.1:25566" }]
+
+[[servers]]
+listen = "0.0.0.0:25565"
+redirect = "127.0.0.1:80"
+
+[[servers]]
+type = "udp"
+listen = "[0:0::0]:25565"
+redirect = "127.0.0.1:19132"
+"#;
+
+        // When
+        let messages = messages(contents);
+
+        // Then
+        assert_eq!(
+            messages,
+            vec!["duplicate listen address 0.0.0.0:25565, already listened on at line 3"]
+        );
+    }
+
+    #[test]
+    fn should_report_duplicate_hostnames_of_a_server_block() {
+        // Given
+        let contents = r#"
+[[servers]]
+listen = "0.0.0.0:25565"
+hosts = [
+    { hostname = "play.example.com", target = "10.0.0.1:25565" },
+    { hostname = "Play.Example.com", target = "10.0.0.2:25565" },
+]
+
+[[servers]]
+type = "tls"
+listen = "0.0.0.0:443"
+
+[[servers.hosts]]
+hostname = "play.example.com"
+target = "10.0.0.1:443"
+alpn = "h2"
+
+[[servers.hosts]]
+hostname = "play.example.com"
+target = "10.0.0.2:443"
+"#;
+
+        // When
+        let messages = messages(contents);
+
+        // Then
+        assert_eq!(
+            messages,
+            vec!["duplicate hostname play.example.com, already routed at line 5"]
+        );
+    }
+
+    #[test]
+    fn should_report_invalid_addresses() {
+        // Given
+        let contents = r#"
+[[servers]]
+listen = ["0.0.0.0", "unix:/run/proxy.sock"]
+redirect = "srv:"
+
+[[servers]]
+type = "udp"
+listen = "unix:/run/proxy-udp.sock"
+redirect = "localhost:70000"
+"#;
+
+        // When
+        let messages = messages(contents);
+
+        // Then
+        assert_eq!(
+            messages,
+            vec![
+                "invalid listen address 0.0.0.0, expected host:port",
+                "invalid target srv:, expected a name after srv:",
+                "invalid listen address unix:/run/proxy-udp.sock, UDP servers cannot listen on a Unix socket",
+                "invalid target localhost:70000, expected a port between 0 and 65535",
+            ]
+        );
+    }
+
+    #[test]
+    fn should_report_unsupported_versions_and_duplicate_names() {
+        // Given
+        let contents = r#"
+version = 2
+
+[[servers]]
+name = "lobby"
+listen = "0.0.0.0:25565"
+hosts = [{ hostname = "localhost", target = "127.0.0.1:25566" }]
+
+[[servers]]
+name = "lobby"
+type = "tcp"
+listen = "0.0.0.0:8080"
+redirect = "127.0.0.1:80"
+"#;
+
+        // When
+        let messages = messages(contents);
+
+        // Then
+        assert_eq!(
+            messages,
+            vec![
+                "unsupported configuration version 2, this proxy reads versions 1 to 1",
+                "duplicate server block name lobby, already used at line 5",
+            ]
+        );
+    }
+
+    #[test]
+    fn should_report_a_min_ttl_above_the_max_ttl() {
+        // Given
+        let contents = "dns = { min_ttl_seconds = 600 }\n";
+
+        // When
+        let messages = messages(contents);
+
+        // Then
+        assert_eq!(
+            messages,
+            vec!["min_ttl_seconds 600 is above max_ttl_seconds 300"]
+        );
+    }
+
+    #[test]
+    fn should_render_the_line_of_a_diagnostic() {
+        // Given
+        let contents = "[[servers]]\nlisten = \"0.0.0.0\"\n";
+        let diagnostic = Diagnostic::error("invalid listen address", Some(21..30));
+
+        // When
+        let rendered = diagnostic.render("config.toml", contents);
+
+        // Then
+        assert_eq!(
+            rendered,
+            "error: invalid listen address\n --> config.toml:2:10\n  |\n2 | listen = \"0.0.0.0\"\n  |          ^^^^^^^^^"
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info};
+
+use crate::backends::host_table::normalize;
+use crate::backends::proxy_connection::traffic;
+use crate::backends::resolver::resolver;
+use crate::backends::stream::{remove_stale_socket, Listener, Stream};
+use crate::backends::supervisor::listeners_status;
+use crate::upgrade::{hand_over, UPGRADE_COMMAND};
+
+#[derive(Error, Debug, PartialEq)]
+pub(crate) enum ControlError {
+    #[error("unknown command {0}")]
+    UnknownCommand(String),
+    #[error("unknown host {0}")]
+    UnknownHost(String),
+    #[error("invalid argument {0}")]
+    InvalidArgument(String),
+}
+
+/// Runtime switches of the proxy, exposed through the control socket.
+#[derive(Default)]
+pub(crate) struct Controls {
+    maintenance: HashMap<String, Vec<Arc<AtomicBool>>>,
+}
+
+impl Controls {
+    pub(crate) fn register_maintenance(&mut self, hostname: &str, flag: Arc<AtomicBool>) {
+        self.maintenance
+            .entry(normalize(hostname))
+            .or_default()
+            .push(flag);
+    }
+
+    /// Executes a single command line, returning the reply sent back to the client.
+    fn execute(&self, line: &str) -> Result<String, ControlError> {
+        let arguments = line.split_whitespace().collect::<Vec<_>>();
+
+        match arguments.as_slice() {
+            ["maintenance", hostname] => {
+                let flags = self.maintenance_flags(hostname)?;
+                let enabled = flags.iter().any(|flag| flag.load(Ordering::Relaxed));
+                Ok(format_state(enabled).to_string())
+            }
+            ["maintenance", hostname, state] => {
+                let enabled = match *state {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(ControlError::InvalidArgument(state.to_string())),
+                };
+
+                for flag in self.maintenance_flags(hostname)? {
+                    flag.store(enabled, Ordering::Relaxed);
+                }
+                info!("Maintenance of {hostname} turned {}", format_state(enabled));
+                Ok(format_state(enabled).to_string())
+            }
+            ["resolver"] => Ok(resolver().metrics().to_string()),
+            ["traffic"] => Ok(traffic().to_string()),
+            ["status"] => Ok(listeners_status()),
+            _ => Err(ControlError::UnknownCommand(line.to_string())),
+        }
+    }
+
+    fn maintenance_flags(&self, hostname: &str) -> Result<&Vec<Arc<AtomicBool>>, ControlError> {
+        self.maintenance
+            .get(&normalize(hostname))
+            .ok_or_else(|| ControlError::UnknownHost(hostname.to_string()))
+    }
+}
+
+fn format_state(enabled: bool) -> &'static str {
+    if enabled {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+/// Starts the control socket at `path`, taking it over from the running proxy on an upgrade.
+pub(crate) fn start_control_socket(
+    path: String,
+    mode: String,
+    controls: Arc<Controls>,
+    upgrade: bool,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        match bind(&path, &mode, upgrade).await {
+            Ok(listener) => listen(Listener::Unix(listener), controls).await,
+            Err(err) => error!("error with control socket; error={err}"),
+        }
+    })
+}
+
+/// Binds the control socket with the octal permissions `mode`. Unless the running proxy is
+/// upgraded, the socket is only replaced when no proxy listens on it.
+async fn bind(path: &str, mode: &str, upgrade: bool) -> std::io::Result<UnixListener> {
+    let mode = u32::from_str_radix(mode, 8).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("invalid control socket mode {mode}"),
+        )
+    })?;
+
+    if !upgrade {
+        remove_stale_socket(path).await?;
+    } else if Path::new(path).exists() {
+        fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    info!("Control socket listening on: {}", path);
+    Ok(listener)
+}
+
+async fn listen(listener: Listener, controls: Arc<Controls>) {
+    loop {
+        let stream = match listener.accept_next().await {
+            Ok((Stream::Unix(stream), _)) => stream,
+            Ok((Stream::Tcp(_), _)) => unreachable!("the control socket is a Unix socket"),
+            Err(err) => {
+                error!("error with control socket, no longer accepting; error={err}");
+                return;
+            }
+        };
+        let controls = Arc::clone(&controls);
+
+        tokio::spawn(async move {
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+
+            while let Ok(Some(line)) = lines.next_line().await {
+                debug!("Received control command: {line}");
+                if line == UPGRADE_COMMAND {
+                    let stream = lines.into_inner().into_inner().reunite(writer).unwrap();
+                    if let Err(err) = hand_over(stream).await {
+                        error!("cannot hand the listeners over; error={err}");
+                    }
+                    return;
+                }
+
+                let reply = match controls.execute(&line) {
+                    Ok(reply) => reply,
+                    Err(err) => format!("error: {err}"),
+                };
+
+                if writer
+                    .write_all(format!("{reply}\n").as_bytes())
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        });
+    }
+}
+
+/// Sends a single command to the control socket of a running proxy and returns its reply.
+pub(crate) async fn send_command(path: &str, command: &str) -> Result<String, std::io::Error> {
+    let stream = UnixStream::connect(path).await?;
+    let (reader, mut writer) = stream.into_split();
+
+    writer.write_all(format!("{command}\n").as_bytes()).await?;
+    writer.shutdown().await?;
+
+    // The proxy closes the connection once the command is answered, replies may span lines
+    let mut reply = String::new();
+    BufReader::new(reader).read_to_string(&mut reply).await?;
+    Ok(reply.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_toggle_maintenance() {
+        // Given
+        let mut controls = Controls::default();
+        let flag = Arc::new(AtomicBool::new(false));
+        controls.register_maintenance("localhost", Arc::clone(&flag));
+
+        // When
+        let reply = controls.execute("maintenance localhost on");
+
+        // Then
+        assert_eq!(reply, Ok("on".to_string()));
+        assert!(flag.load(Ordering::Relaxed));
+        assert_eq!(
+            controls.execute("maintenance localhost"),
+            Ok("on".to_string())
+        );
+    }
+
+    #[test]
+    fn should_match_hostnames_as_the_routes_do() {
+        // Given
+        let mut controls = Controls::default();
+        let flag = Arc::new(AtomicBool::new(false));
+        controls.register_maintenance("play.example.com", Arc::clone(&flag));
+
+        // When
+        let reply = controls.execute("maintenance Play.Example.com. on");
+
+        // Then
+        assert_eq!(reply, Ok("on".to_string()));
+        assert!(flag.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn should_bind_the_control_socket_with_its_mode() {
+        // Given
+        let path = std::env::temp_dir().join(format!("proxy-control-{}.sock", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        // When
+        let listener = bind(path, "600", false).await.unwrap();
+
+        // Then
+        let mode = fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        drop(listener);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_not_take_the_control_socket_of_a_running_proxy() {
+        // Given
+        let path = std::env::temp_dir().join(format!("proxy-running-{}.sock", std::process::id()));
+        let path = path.to_str().unwrap();
+        let running = bind(path, "600", false).await.unwrap();
+
+        // When
+        let err = bind(path, "600", false).await.unwrap_err();
+
+        // Then
+        assert_eq!(err.kind(), std::io::ErrorKind::AddrInUse);
+        drop(running);
+        assert!(bind(path, "600", false).await.is_ok());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_reject_unknown_host() {
+        // Given
+        let controls = Controls::default();
+
+        // When
+        let reply = controls.execute("maintenance example.com off");
+
+        // Then
+        assert_eq!(
+            reply,
+            Err(ControlError::UnknownHost("example.com".to_string()))
+        );
+    }
+}
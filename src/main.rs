@@ -1,16 +1,33 @@
 use std::error::Error;
+use std::sync::Arc;
 
-use clap::Parser;
-use tracing::error;
+use clap::{Parser, Subcommand, ValueEnum};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info};
 
-use crate::backends::minecraft::start_minecraft_proxy;
+use crate::backends::minecraft::{reload_player_lists, start_minecraft_proxy, AccessControl};
+use backends::bedrock::start_bedrock_proxy;
+use backends::http::start_http_proxy;
+use backends::inherited::close_unused_listeners;
+use backends::resolver::configure_resolver;
+use backends::supervisor::{bind_failures, configure_supervisor, drain};
 use backends::tcp::start_tcp_proxy;
-use configuration::{read_config, Servers};
+use backends::tls::start_tls_proxy;
+use backends::udp::start_udp_proxy;
+use check::check_config;
+use configuration::{read_config, Config, Servers};
+use control::{send_command, start_control_socket, Controls};
 use logging::enable_logging;
+use systemd::{notify_ready, notify_reloading, notify_stopping, receive_listen_fds, run_watchdog};
+use upgrade::{take_over, Handover};
 
 mod backends;
+mod check;
 mod configuration;
+mod control;
 mod logging;
+mod systemd;
+mod upgrade;
 
 #[derive(Debug, Parser)]
 #[command(version, about, long_about = None)]
@@ -18,8 +35,39 @@ struct Cli {
     #[arg(short, long, action = clap::ArgAction::Count)]
     verbose: u8,
 
-    #[arg(short, long, default_value = "config.toml")]
+    #[arg(short, long, global = true, default_value = "config.toml")]
     config: String,
+
+    /// Take over the listening sockets of the proxy running with the same control socket, which
+    /// stops accepting and exits once its connections are closed
+    #[arg(long)]
+    upgrade: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Show or change the maintenance mode of a host on the running proxy
+    Maintenance {
+        hostname: String,
+        state: Option<MaintenanceState>,
+    },
+    /// Show the DNS resolution counters of the running proxy
+    Resolver,
+    /// Show the number of forwarded connections and bytes of the running proxy
+    Traffic,
+    /// Show the state of every listener of the running proxy
+    Status,
+    /// Validate the configuration file and try to reach the targets of its routes
+    Check,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+enum MaintenanceState {
+    On,
+    Off,
 }
 
 #[tokio::main]
@@ -27,21 +75,186 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let args = Cli::parse();
     enable_logging(args.verbose);
 
-    let config = read_config(args.config);
-
-    match config {
-        Ok(config) => {
-            let servers = config.servers.iter().cloned().map(|server| match server {
-                Servers::Minecraft { listen, hosts } => start_minecraft_proxy(listen, hosts),
-                Servers::Tcp { listen, redirect } => start_tcp_proxy(listen, redirect),
-            });
+    if let Some(Command::Check) = args.command {
+        let valid = check_config(&args.config).await;
+        std::process::exit(if valid { 0 } else { 1 });
+    }
 
-            futures::future::join_all(servers).await;
-        }
+    let config = match read_config(&args.config) {
+        Ok(config) => config,
         Err(err) => {
             error!("error while reading configuration; error={err}");
+            std::process::exit(1);
+        }
+    };
+
+    match args.command {
+        None if args.upgrade => {
+            let Some(path) = config.control_socket.as_ref() else {
+                error!("no control socket configured, it is needed to upgrade");
+                std::process::exit(1);
+            };
+            match take_over(path).await {
+                Ok(handover) => start_servers(config, Some(handover)).await,
+                Err(err) => {
+                    error!("cannot take over the listeners of the running proxy; error={err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => start_servers(config, None).await,
+        Some(command) => {
+            let command = match command {
+                Command::Maintenance { hostname, state } => match state {
+                    None => format!("maintenance {hostname}"),
+                    Some(MaintenanceState::On) => format!("maintenance {hostname} on"),
+                    Some(MaintenanceState::Off) => format!("maintenance {hostname} off"),
+                },
+                Command::Resolver => "resolver".to_string(),
+                Command::Traffic => "traffic".to_string(),
+                Command::Status => "status".to_string(),
+                Command::Check => unreachable!("the configuration is checked above"),
+            };
+
+            match config.control_socket {
+                Some(path) => {
+                    let reply = send_command(&path, &command).await?;
+                    println!("{reply}");
+                    // Lets monitoring tell a degraded proxy from the exit status
+                    if command == "status" && reply.starts_with("degraded") {
+                        std::process::exit(1);
+                    }
+                }
+                None => error!("no control socket configured"),
+            }
         }
     }
 
     Ok(())
 }
+
+async fn start_servers(config: Config, handover: Option<Handover>) {
+    configure_resolver(config.dns.clone());
+    configure_supervisor(config.supervisor.clone());
+    receive_listen_fds();
+    let global_access =
+        AccessControl::from_files(config.whitelist.as_ref(), config.banned_players.as_ref());
+    let mut controls = Controls::default();
+
+    let mut servers = config
+        .servers
+        .iter()
+        .cloned()
+        .map(|server| match server {
+            Servers::Minecraft {
+                listener,
+                hosts,
+                query,
+            } => start_minecraft_proxy(listener, hosts, query, &global_access, &mut controls),
+            Servers::Tcp {
+                listener,
+                redirect,
+                outbound,
+            } => start_tcp_proxy(listener, redirect, outbound),
+            Servers::Tls { listener, hosts } => start_tls_proxy(listener, hosts),
+            Servers::Http {
+                listener,
+                hosts,
+                forwarded_headers,
+            } => start_http_proxy(listener, hosts, forwarded_headers),
+            Servers::Udp {
+                listener,
+                redirect,
+                idle_timeout_seconds,
+                max_sessions,
+            } => start_udp_proxy(listener, redirect, idle_timeout_seconds, max_sessions),
+            Servers::Bedrock {
+                listener,
+                hosts,
+                pong,
+                idle_timeout_seconds,
+                max_sessions,
+            } => start_bedrock_proxy(listener, hosts, pong, idle_timeout_seconds, max_sessions),
+        })
+        .collect::<Vec<_>>();
+
+    if let Some(path) = config.control_socket {
+        let mode = config.control_socket_mode;
+        let upgrade = handover.is_some();
+        servers.push(start_control_socket(
+            path,
+            mode,
+            Arc::new(controls),
+            upgrade,
+        ));
+    }
+
+    let failures = bind_failures().await;
+    if config.supervisor.exit_on_bind_failure && !failures.is_empty() {
+        error!(
+            "{} listeners failed to bind at startup, exiting; failures={}",
+            failures.len(),
+            failures.join(", ")
+        );
+        std::process::exit(1);
+    }
+    close_unused_listeners();
+    if let Some(handover) = handover {
+        if let Err(err) = handover.complete().await {
+            error!("cannot tell the previous process this one listens; error={err}");
+        }
+    }
+    notify_ready();
+    servers.push(tokio::spawn(run_watchdog()));
+
+    tokio::select! {
+        _ = futures::future::join_all(servers) => {}
+        _ = handle_signals() => {}
+        _ = drain() => {}
+    }
+}
+
+/// Reloads the player lists on `SIGHUP`, and returns on `SIGTERM` or `SIGINT` to stop the proxy,
+/// telling systemd about both.
+async fn handle_signals() {
+    let signals = [
+        SignalKind::hangup(),
+        SignalKind::terminate(),
+        SignalKind::interrupt(),
+    ]
+    .map(signal);
+    let [Ok(mut hangup), Ok(mut terminate), Ok(mut interrupt)] = signals else {
+        error!("cannot handle signals, SIGHUP does not reload the player lists");
+        return std::future::pending().await;
+    };
+
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => {
+                info!("Reloading the player lists");
+                notify_reloading();
+                reload_player_lists();
+                notify_ready();
+            }
+            _ = terminate.recv() => break,
+            _ = interrupt.recv() => break,
+        }
+    }
+    info!("Stopping");
+    notify_stopping();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_accept_the_config_after_a_subcommand() {
+        // When
+        let args = Cli::try_parse_from(["proxy", "check", "--config", "x.toml"]).unwrap();
+
+        // Then
+        assert!(matches!(args.command, Some(Command::Check)));
+        assert_eq!(args.config, "x.toml");
+    }
+}
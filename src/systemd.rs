@@ -0,0 +1,212 @@
+use std::ffi::OsStr;
+use std::os::fd::{FromRawFd, RawFd};
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{self, UnixDatagram};
+use std::process;
+use std::time::Duration;
+use std::{env, fs, io};
+
+use socket2::Socket;
+use tracing::{debug, info, warn};
+
+use crate::backends::inherited::inherit_listener;
+use crate::backends::supervisor::listeners_status;
+
+/// First file descriptor passed by systemd, see sd_listen_fds(3).
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Keeps the sockets passed by systemd with socket activation, so listeners bound to the same
+/// addresses use them instead of binding new sockets.
+pub(crate) fn receive_listen_fds() {
+    if !is_for_this_process("LISTEN_PID") {
+        return;
+    }
+    let Some(count) = env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|count| count.parse::<RawFd>().ok())
+    else {
+        return;
+    };
+
+    for fd in LISTEN_FDS_START..LISTEN_FDS_START + count {
+        // SAFETY: systemd passes the file descriptors from 3 on to this process, which owns them
+        let socket = unsafe { Socket::from_raw_fd(fd) };
+        if let Err(err) = socket.set_cloexec(true) {
+            warn!("Cannot use the socket {fd} passed by systemd; error={err}");
+            continue;
+        }
+        inherit_listener(socket);
+    }
+    info!("Received {count} sockets from systemd");
+}
+
+/// Tells systemd the proxy is ready, with the health of its listeners.
+pub(crate) fn notify_ready() {
+    notify(&format!("READY=1\nSTATUS={}", health()));
+}
+
+/// Tells systemd the proxy is reloading, `READY=1` ends the reload.
+pub(crate) fn notify_reloading() {
+    notify(&format!("RELOADING=1\nMONOTONIC_USEC={}", monotonic_usec()));
+}
+
+pub(crate) fn notify_stopping() {
+    notify("STOPPING=1");
+}
+
+/// Variables systemd gave this process to be notified, passed to the process taking the listeners
+/// over, which notifies systemd in place of this one.
+pub(crate) fn notify_environment() -> Vec<(String, String)> {
+    let mut environment = Vec::new();
+    if let Ok(path) = env::var("NOTIFY_SOCKET") {
+        environment.push(("NOTIFY_SOCKET".to_string(), path));
+    }
+    if let (Some(_), Ok(usec)) = (watchdog_interval(), env::var("WATCHDOG_USEC")) {
+        environment.push(("WATCHDOG_USEC".to_string(), usec));
+    }
+    environment
+}
+
+/// Notifies systemd with the variables of the process which handed its listeners over.
+pub(crate) fn inherit_notify_environment(environment: Vec<(String, String)>) {
+    for (name, value) in environment {
+        if name == "NOTIFY_SOCKET" || name == "WATCHDOG_USEC" {
+            env::set_var(&name, value);
+        }
+    }
+    if env::var_os("WATCHDOG_USEC").is_some() {
+        env::set_var("WATCHDOG_PID", process::id().to_string());
+    }
+}
+
+/// Checks systemd accepts the process `pid` as the main process of the service, if the proxy is
+/// one: the process must be in the control group of the service, unless the proxy runs as root.
+pub(crate) fn check_main_pid(pid: i32) -> io::Result<()> {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    if env::var_os("NOTIFY_SOCKET").is_none() || unsafe { libc::geteuid() } == 0 {
+        return Ok(());
+    }
+    if fs::read_to_string("/proc/self/cgroup")?
+        != fs::read_to_string(format!("/proc/{pid}/cgroup"))?
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "systemd only accepts a new main process in the control group of the service, unless \
+             the proxy runs as root",
+        ));
+    }
+    Ok(())
+}
+
+/// Makes the process `pid` the main process of the service, this process no longer notifying
+/// systemd, so the service goes on when this process exits.
+pub(crate) fn notify_main_pid(pid: i32) {
+    notify(&format!("MAINPID={pid}"));
+    env::remove_var("NOTIFY_SOCKET");
+}
+
+/// Pings the watchdog at half its interval when systemd set `WatchdogSec=`, updating the health
+/// of the listeners along.
+pub(crate) async fn run_watchdog() {
+    let Some(interval) = watchdog_interval() else {
+        return;
+    };
+    debug!("Pinging the systemd watchdog every {interval:?}");
+
+    let mut ticks = tokio::time::interval(interval / 2);
+    loop {
+        ticks.tick().await;
+        notify(&format!("WATCHDOG=1\nSTATUS={}", health()));
+    }
+}
+
+fn watchdog_interval() -> Option<Duration> {
+    if env::var_os("WATCHDOG_PID").is_some() && !is_for_this_process("WATCHDOG_PID") {
+        return None;
+    }
+    env::var("WATCHDOG_USEC")
+        .ok()
+        .and_then(|usec| usec.parse().ok())
+        .filter(|usec| *usec > 0)
+        .map(Duration::from_micros)
+}
+
+/// Whether the variable holds the PID of this process, systemd variables are not meant for the
+/// children of the process which inherit them.
+fn is_for_this_process(variable: &str) -> bool {
+    env::var(variable).is_ok_and(|pid| pid == process::id().to_string())
+}
+
+/// First line of the listener status, such as `healthy 2/2 listening`.
+fn health() -> String {
+    listeners_status()
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Sends a notification to systemd, doing nothing when the service is not of `Type=notify`.
+fn notify(state: &str) {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+
+    let result = notify_address(&path).and_then(|address| {
+        UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &address)?;
+        Ok(())
+    });
+    if let Err(err) = result {
+        warn!("Cannot notify systemd; error={err}");
+    }
+}
+
+/// Address of the notify socket, a leading `@` naming an abstract socket.
+#[cfg(target_os = "linux")]
+fn notify_address(path: &OsStr) -> io::Result<net::SocketAddr> {
+    match path.as_encoded_bytes().strip_prefix(b"@") {
+        Some(name) => net::SocketAddr::from_abstract_name(name),
+        None => net::SocketAddr::from_pathname(path),
+    }
+}
+
+/// Address of the notify socket, abstract sockets only existing on Linux.
+#[cfg(not(target_os = "linux"))]
+fn notify_address(path: &OsStr) -> io::Result<net::SocketAddr> {
+    net::SocketAddr::from_pathname(path)
+}
+
+fn monotonic_usec() -> u64 {
+    // SAFETY: clock_gettime only writes to the given struct
+    let time = unsafe {
+        let mut time = std::mem::zeroed::<libc::timespec>();
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time);
+        time
+    };
+    time.tv_sec as u64 * 1_000_000 + time.tv_nsec as u64 / 1_000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_send_notifications_to_the_notify_socket() {
+        // Given
+        let path = env::temp_dir().join(format!("proxy-notify-{}.sock", process::id()));
+        let _ = std::fs::remove_file(&path);
+        let socket = UnixDatagram::bind(&path).unwrap();
+        env::set_var("NOTIFY_SOCKET", &path);
+
+        // When
+        notify_stopping();
+
+        // Then
+        let mut buffer = [0; 64];
+        let length = socket.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..length], b"STOPPING=1");
+        env::remove_var("NOTIFY_SOCKET");
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,326 @@
+use std::io;
+use std::mem::{size_of, size_of_val};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+use socket2::Socket;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader, Interest};
+use tokio::net::unix::UCred;
+use tokio::net::UnixStream;
+use tracing::{info, warn};
+
+use crate::backends::inherited::inherit_listener;
+use crate::backends::supervisor::{listening_sockets, stop_listeners};
+use crate::systemd::{
+    check_main_pid, inherit_notify_environment, notify_environment, notify_main_pid,
+};
+
+/// Control command asking the running proxy for its listening sockets.
+pub(crate) const UPGRADE_COMMAND: &str = "upgrade";
+
+/// Sent by the new process once it listens, for the previous one to stop accepting.
+const READY: &str = "ready";
+
+/// Sockets passed in a single message, below the `SCM_MAX_FD` limit of Linux.
+const SOCKETS_PER_MESSAGE: usize = 128;
+
+/// Flags of the messages sent, other systems having no `MSG_NOSIGNAL` while Rust ignores SIGPIPE.
+#[cfg(target_os = "linux")]
+const SEND_FLAGS: libc::c_int = libc::MSG_NOSIGNAL;
+#[cfg(not(target_os = "linux"))]
+const SEND_FLAGS: libc::c_int = 0;
+
+/// Flags of the messages received, other systems setting close-on-exec after receiving.
+#[cfg(target_os = "linux")]
+const RECEIVE_FLAGS: libc::c_int = libc::MSG_CMSG_CLOEXEC;
+#[cfg(not(target_os = "linux"))]
+const RECEIVE_FLAGS: libc::c_int = 0;
+
+/// Connection to the running proxy which handed its listening sockets over.
+pub(crate) struct Handover {
+    stream: UnixStream,
+}
+
+/// Asks the proxy running with the control socket `path` for its listening sockets, which the
+/// listeners of this process use instead of binding the same addresses.
+pub(crate) async fn take_over(path: &str) -> io::Result<Handover> {
+    let mut stream = UnixStream::connect(path).await?;
+    stream
+        .write_all(format!("{UPGRADE_COMMAND}\n").as_bytes())
+        .await?;
+
+    let mut received = 0;
+    loop {
+        let (sockets, more) = stream
+            .async_io(Interest::READABLE, || receive_fds(stream.as_raw_fd()))
+            .await?;
+        received += sockets.len();
+        for socket in sockets {
+            inherit_listener(Socket::from(socket));
+        }
+        if !more {
+            break;
+        }
+    }
+    info!("Received {received} listening sockets from the running proxy");
+    inherit_notify_environment(receive_environment(&mut BufReader::new(&mut stream)).await?);
+
+    Ok(Handover { stream })
+}
+
+impl Handover {
+    /// Tells the previous process this one listens, so it stops accepting and drains its
+    /// connections.
+    pub(crate) async fn complete(mut self) -> io::Result<()> {
+        self.stream.write_all(format!("{READY}\n").as_bytes()).await
+    }
+}
+
+/// Passes the listening sockets to a new process, then stops the listeners once it listens too,
+/// the new process becoming the main process of the systemd service. The listeners go on when the
+/// new process fails to start.
+pub(crate) async fn hand_over(mut stream: UnixStream) -> io::Result<()> {
+    let peer = stream.peer_cred()?;
+    check_peer(&peer)?;
+    let pid = peer
+        .pid()
+        .ok_or_else(|| io::Error::other("the new process has no known PID"))?;
+    check_main_pid(pid)?;
+    let sockets = listening_sockets();
+    info!(
+        "Handing {} listening sockets over to a new process",
+        sockets.len()
+    );
+
+    let fds = sockets.iter().map(AsRawFd::as_raw_fd).collect::<Vec<_>>();
+    let mut chunks = fds.chunks(SOCKETS_PER_MESSAGE).peekable();
+    // A message is sent even without sockets, telling there are no more
+    loop {
+        let chunk = chunks.next().unwrap_or_default();
+        let more = chunks.peek().is_some();
+        stream
+            .async_io(Interest::WRITABLE, || {
+                send_fds(stream.as_raw_fd(), chunk, more)
+            })
+            .await?;
+        if !more {
+            break;
+        }
+    }
+    drop(sockets);
+    send_environment(&mut stream, &notify_environment()).await?;
+
+    let mut reply = String::new();
+    BufReader::new(&mut stream).read_line(&mut reply).await?;
+    if reply.trim_end() != READY {
+        warn!("The new process did not start, listening on");
+        return Ok(());
+    }
+
+    info!("The new process listens, draining the connections");
+    notify_main_pid(pid);
+    stop_listeners();
+    Ok(())
+}
+
+/// Only processes of the user running the proxy, or of root, take its listening sockets over.
+fn check_peer(peer: &UCred) -> io::Result<()> {
+    let uid = peer.uid();
+    // SAFETY: geteuid has no preconditions and cannot fail
+    let own_uid = unsafe { libc::geteuid() };
+    if uid != own_uid && uid != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("a process of user {uid} cannot take the listening sockets over"),
+        ));
+    }
+    Ok(())
+}
+
+/// Sends the variables as `NAME=value` lines, ending with an empty line.
+async fn send_environment(
+    stream: &mut (impl AsyncWrite + Unpin),
+    environment: &[(String, String)],
+) -> io::Result<()> {
+    let mut lines = environment
+        .iter()
+        .map(|(name, value)| format!("{name}={value}\n"))
+        .collect::<String>();
+    lines.push('\n');
+    stream.write_all(lines.as_bytes()).await
+}
+
+async fn receive_environment(
+    stream: &mut (impl AsyncBufRead + Unpin),
+) -> io::Result<Vec<(String, String)>> {
+    let mut environment = Vec::new();
+    loop {
+        let mut line = String::new();
+        stream.read_line(&mut line).await?;
+        let Some((name, value)) = line.trim_end().split_once('=') else {
+            return Ok(environment);
+        };
+        environment.push((name.to_string(), value.to_string()));
+    }
+}
+
+/// Sends the file descriptors with `SCM_RIGHTS`, along with a byte telling whether more follow.
+fn send_fds(socket: RawFd, fds: &[RawFd], more: bool) -> io::Result<()> {
+    let data = [u8::from(more)];
+    let mut iov = libc::iovec {
+        iov_base: data.as_ptr() as *mut libc::c_void,
+        iov_len: data.len(),
+    };
+    // SAFETY: CMSG_SPACE only computes a size
+    let space = unsafe { libc::CMSG_SPACE(size_of_val(fds) as u32) } as usize;
+    // Control messages are aligned as the u64 of the buffer
+    let mut control = vec![0u64; space.div_ceil(size_of::<u64>())];
+
+    // SAFETY: the message points to the data and control buffers living for the duration of the
+    // call, the control buffer being large enough for the file descriptors
+    let result = unsafe {
+        let mut message = std::mem::zeroed::<libc::msghdr>();
+        message.msg_iov = &mut iov;
+        message.msg_iovlen = 1;
+        if !fds.is_empty() {
+            message.msg_control = control.as_mut_ptr().cast();
+            message.msg_controllen = space as _;
+            let header = libc::CMSG_FIRSTHDR(&message);
+            (*header).cmsg_level = libc::SOL_SOCKET;
+            (*header).cmsg_type = libc::SCM_RIGHTS;
+            (*header).cmsg_len = libc::CMSG_LEN(size_of_val(fds) as u32) as _;
+            std::ptr::copy_nonoverlapping(
+                fds.as_ptr(),
+                libc::CMSG_DATA(header).cast::<RawFd>(),
+                fds.len(),
+            );
+        }
+        libc::sendmsg(socket, &message, SEND_FLAGS)
+    };
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Receives the file descriptors sent by `send_fds`, and whether more follow.
+fn receive_fds(socket: RawFd) -> io::Result<(Vec<OwnedFd>, bool)> {
+    let mut data = [0u8; 1];
+    let mut iov = libc::iovec {
+        iov_base: data.as_mut_ptr().cast(),
+        iov_len: data.len(),
+    };
+    // SAFETY: CMSG_SPACE only computes a size
+    let space =
+        unsafe { libc::CMSG_SPACE((SOCKETS_PER_MESSAGE * size_of::<RawFd>()) as u32) } as usize;
+    let mut control = vec![0u64; space.div_ceil(size_of::<u64>())];
+
+    let mut fds = Vec::new();
+    // SAFETY: the message points to buffers living for the duration of the call, the received
+    // file descriptors are owned by this process from now on
+    let (received, flags) = unsafe {
+        let mut message = std::mem::zeroed::<libc::msghdr>();
+        message.msg_iov = &mut iov;
+        message.msg_iovlen = 1;
+        message.msg_control = control.as_mut_ptr().cast();
+        message.msg_controllen = space as _;
+        let received = libc::recvmsg(socket, &mut message, RECEIVE_FLAGS);
+        if received == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut header = libc::CMSG_FIRSTHDR(&message);
+        while !header.is_null() {
+            if (*header).cmsg_level == libc::SOL_SOCKET && (*header).cmsg_type == libc::SCM_RIGHTS {
+                let length = (*header).cmsg_len as usize - libc::CMSG_LEN(0) as usize;
+                let data = libc::CMSG_DATA(header).cast::<RawFd>();
+                for index in 0..length / size_of::<RawFd>() {
+                    fds.push(OwnedFd::from_raw_fd(data.add(index).read_unaligned()));
+                }
+            }
+            header = libc::CMSG_NXTHDR(&message, header);
+        }
+        (received, message.msg_flags)
+    };
+    #[cfg(not(target_os = "linux"))]
+    for fd in &fds {
+        // SAFETY: fcntl only sets the flags of a file descriptor owned by this process
+        if unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    if received == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the running proxy closed the connection",
+        ));
+    }
+    if flags & libc::MSG_CTRUNC != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "too many sockets in a message",
+        ));
+    }
+    Ok((fds, data[0] == 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::os::unix::net::UnixStream;
+
+    #[test]
+    fn should_pass_listening_sockets_between_processes() {
+        // Given
+        let (old, new) = UnixStream::pair().unwrap();
+        let listeners = [
+            TcpListener::bind("127.0.0.1:0").unwrap(),
+            TcpListener::bind("127.0.0.1:0").unwrap(),
+        ];
+        let fds = listeners.each_ref().map(AsRawFd::as_raw_fd);
+
+        // When
+        send_fds(old.as_raw_fd(), &fds, true).unwrap();
+        send_fds(old.as_raw_fd(), &[], false).unwrap();
+        let (first, more) = receive_fds(new.as_raw_fd()).unwrap();
+        let (last, no_more) = receive_fds(new.as_raw_fd()).unwrap();
+
+        // Then
+        let addresses = first
+            .into_iter()
+            .map(|fd| Socket::from(fd).local_addr().unwrap().as_socket().unwrap())
+            .collect::<Vec<_>>();
+        let expected = listeners
+            .each_ref()
+            .map(|listener| listener.local_addr().unwrap());
+        assert_eq!(addresses, expected);
+        assert!(more);
+        assert!(last.is_empty());
+        assert!(!no_more);
+    }
+
+    #[tokio::test]
+    async fn should_pass_the_notify_environment() {
+        // Given
+        let (mut old, new) = tokio::io::duplex(1024);
+        let environment = vec![
+            (
+                "NOTIFY_SOCKET".to_string(),
+                "/run/systemd/notify".to_string(),
+            ),
+            ("WATCHDOG_USEC".to_string(), "30000000".to_string()),
+        ];
+
+        // When
+        send_environment(&mut old, &environment).await.unwrap();
+        send_environment(&mut old, &[]).await.unwrap();
+        let mut new = BufReader::new(new);
+        let received = receive_environment(&mut new).await.unwrap();
+        let empty = receive_environment(&mut new).await.unwrap();
+
+        // Then
+        assert_eq!(received, environment);
+        assert!(empty.is_empty());
+    }
+}